@@ -0,0 +1,103 @@
+//! Times the ways --fixed could search a large made-up log against the one
+//! ircgrep uses. `cargo bench` prints how long each takes to go through every
+//! line.
+
+use std::time::{Duration, Instant};
+
+// The bench can't depend on a binary crate, so it builds the module itself.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/query.rs"]
+mod query;
+
+use query::CompiledQuery;
+
+const WORDS: &[&str] = &[
+    "did", "you", "try", "git", "rebase", "--onto", "the", "branch", "merge", "conflict", "is",
+    "gone", "now", "stash", "it", "first", "then", "bisect", "worked", "for", "me", "thanks",
+];
+
+/// Lines like the messages in a busy channel, the same on every run.
+fn make_log(lines: usize) -> Vec<String> {
+    let mut seed = 0x2545_f491_u32;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as usize
+    };
+
+    (0..lines)
+        .map(|_| {
+            let len = 4 + next() % 16;
+            let words: Vec<&str> = (0..len).map(|_| WORDS[next() % WORDS.len()]).collect();
+            words.join(" ")
+        })
+        .collect()
+}
+
+/// Runs `find` over every line, a few times over, and prints the fastest
+/// round along with how many matches it found.
+fn time(name: &str, lines: &[String], mut find: impl FnMut(&str) -> usize) {
+    let mut best = Duration::MAX;
+    let mut matches = 0;
+
+    for _ in 0..5 {
+        let start = Instant::now();
+        matches = lines.iter().map(|l| find(l)).sum();
+        best = best.min(start.elapsed());
+    }
+
+    println!("{:<32} {:>8.2?} {:>9} matches", name, best, matches);
+}
+
+fn main() {
+    let lines = make_log(200_000);
+
+    let needle = "rebase";
+    println!("{} lines, one fixed string", lines.len());
+
+    // What --fixed did before, collecting the ranges the same way.
+    time("match_indices", &lines, |l| {
+        let ranges: Vec<(usize, usize)> = l
+            .match_indices(needle)
+            .map(|(i, m)| (i, i + m.len()))
+            .collect();
+        ranges.len()
+    });
+
+    let fixed = CompiledQuery::fixed(needle);
+    time("memmem::Finder", &lines, |l| fixed.find(l).ranges.len());
+
+    println!();
+    let needles = [
+        "rebase",
+        "merge",
+        "stash",
+        "bisect",
+        "cherry-pick",
+        "reflog",
+    ];
+    println!("{} lines, {} fixed strings", lines.len(), needles.len());
+
+    // What several -e patterns came to before: every string searched for on
+    // its own, and the matches put in order.
+    time("match_indices for each string", &lines, |l| {
+        let mut ranges: Vec<(usize, usize)> = needles
+            .iter()
+            .flat_map(|n| l.match_indices(n).map(|(i, m)| (i, i + m.len())))
+            .collect();
+        ranges.sort_unstable();
+        ranges.len()
+    });
+
+    // Prefers the first string that matches rather than the longest, and
+    // doesn't say which one it found.
+    let escaped: Vec<String> = needles.iter().map(|n| regex::escape(n)).collect();
+    let alternation = CompiledQuery::regex(&escaped.join("|")).unwrap();
+    time("regex alternation", &lines, |l| {
+        alternation.find(l).ranges.len()
+    });
+
+    let set = CompiledQuery::fixed_set(&needles);
+    time("aho-corasick", &lines, |l| set.find(l).ranges.len());
+}
@@ -0,0 +1,127 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path;
+
+/// How far --since-last got in each log: the time stamp of the last line
+/// seen, keyed by the path of the log. The state is kept as a JSON object
+/// like `{"/home/osse/logs/irc.libera.#rust.weechatlog": "2020-06-22 11:18:46"}`.
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    file: path::PathBuf,
+    last: BTreeMap<String, String>,
+    /// What this run has seen, which becomes `last` when saved.
+    seen: RefCell<BTreeMap<String, String>>,
+}
+
+impl Bookmarks {
+    pub fn parse(file: path::PathBuf, s: &str) -> Result<Bookmarks, String> {
+        let value: serde_json::Value = serde_json::from_str(s).map_err(|e| e.to_string())?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| String::from("expected an object"))?;
+
+        let mut last = BTreeMap::new();
+        for (log, timestamp) in object {
+            let timestamp = timestamp
+                .as_str()
+                .ok_or_else(|| format!("the time stamp for {} must be a string", log))?;
+            last.insert(log.clone(), timestamp.to_string());
+        }
+
+        Ok(Bookmarks {
+            file,
+            last,
+            seen: RefCell::default(),
+        })
+    }
+
+    /// Reads the state file. Before the first run there isn't one, and then
+    /// every line is new.
+    pub fn load(file: path::PathBuf) -> Result<Bookmarks, String> {
+        match fs::read_to_string(&file) {
+            Ok(s) => {
+                Bookmarks::parse(file.clone(), &s).map_err(|e| format!("{}: {}", file.display(), e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Bookmarks {
+                file,
+                ..Default::default()
+            }),
+            Err(e) => Err(format!("{}: {}", file.display(), e)),
+        }
+    }
+
+    /// Whether a line in `log` is newer than the last one seen in it before,
+    /// remembering it as seen. Lines from the same second as the last line
+    /// seen count as old.
+    pub fn is_new(&self, log: &path::Path, timestamp: &str) -> bool {
+        let key = log.to_string_lossy();
+
+        let mut seen = self.seen.borrow_mut();
+        match seen.get_mut(key.as_ref()) {
+            Some(t) if t.as_str() >= timestamp => {}
+            Some(t) => *t = timestamp.to_string(),
+            None => {
+                seen.insert(key.to_string(), timestamp.to_string());
+            }
+        }
+
+        self.last
+            .get(key.as_ref())
+            .is_none_or(|last| timestamp > last.as_str())
+    }
+
+    /// Writes back the state with what this run has seen, creating the
+    /// directory for it if need be. Logs this run didn't look at keep their
+    /// old bookmarks.
+    pub fn save(&self) -> io::Result<()> {
+        let mut state = self.last.clone();
+        for (log, timestamp) in self.seen.borrow().iter() {
+            let t = state.entry(log.clone()).or_default();
+            if timestamp > t {
+                *t = timestamp.clone();
+            }
+        }
+
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.file, serde_json::json!(state).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_last() {
+        let file = std::env::temp_dir()
+            .join(format!("ircgrep-{}-bookmarks", std::process::id()))
+            .join("since-last.json");
+        let rust = path::Path::new("irc.libera.#rust.weechatlog");
+        let git = path::Path::new("irc.libera.#git.weechatlog");
+
+        let first = Bookmarks::load(file.clone()).unwrap();
+        assert!(first.is_new(rust, "2020-06-22 11:00:00"));
+        assert!(first.is_new(rust, "2020-06-22 11:00:01"));
+        assert!(first.is_new(git, "2020-06-22 09:00:00"));
+        first.save().unwrap();
+
+        let second = Bookmarks::load(file.clone()).unwrap();
+        assert!(!second.is_new(rust, "2020-06-22 11:00:00"));
+        assert!(!second.is_new(rust, "2020-06-22 11:00:01"));
+        assert!(second.is_new(rust, "2020-06-22 11:00:02"));
+        second.save().unwrap();
+
+        let third = Bookmarks::load(file.clone()).unwrap();
+        assert!(!third.is_new(rust, "2020-06-22 11:00:02"));
+        assert!(!third.is_new(git, "2020-06-22 09:00:00"));
+
+        assert!(Bookmarks::parse(file.clone(), "[]").is_err());
+        assert!(Bookmarks::parse(file.clone(), "{\"a\": 1}").is_err());
+
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+use std::path;
+
+/// Defaults read from the config file, for options that weren't given on the
+/// command line. `None` means the file doesn't say.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Defaults {
+    pub network: Option<String>,
+    pub strip_joins: Option<bool>,
+    pub content_only: Option<bool>,
+    pub skip_blank: Option<bool>,
+    pub strip_timestamps: Option<bool>,
+    pub render_actions: Option<bool>,
+}
+
+/// The config file: defaults at the top, and defaults for single channels in
+/// sections like `[channel."#rust"]` that take precedence over them.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    global: Defaults,
+    channels: Vec<(String, Defaults)>,
+}
+
+impl Defaults {
+    fn parse(table: &toml::Table) -> Result<Defaults, String> {
+        let mut defaults = Defaults::default();
+
+        for (key, value) in table {
+            let flag = match key.as_str() {
+                "network" => {
+                    let network = value
+                        .as_str()
+                        .ok_or_else(|| String::from("network must be a string"))?;
+                    defaults.network = Some(network.to_string());
+                    continue;
+                }
+                "strip_joins" => &mut defaults.strip_joins,
+                "content_only" => &mut defaults.content_only,
+                "skip_blank" => &mut defaults.skip_blank,
+                "strip_timestamps" => &mut defaults.strip_timestamps,
+                "render_actions" => &mut defaults.render_actions,
+                _ => return Err(format!("unknown setting '{}'", key)),
+            };
+            *flag = Some(
+                value
+                    .as_bool()
+                    .ok_or_else(|| format!("{} must be true or false", key))?,
+            );
+        }
+
+        Ok(defaults)
+    }
+
+    /// These defaults with whatever they leave out taken from `base`.
+    fn or(self, base: &Defaults) -> Defaults {
+        Defaults {
+            network: self.network.or_else(|| base.network.clone()),
+            strip_joins: self.strip_joins.or(base.strip_joins),
+            content_only: self.content_only.or(base.content_only),
+            skip_blank: self.skip_blank.or(base.skip_blank),
+            strip_timestamps: self.strip_timestamps.or(base.strip_timestamps),
+            render_actions: self.render_actions.or(base.render_actions),
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(s: &str) -> Result<Config, String> {
+        let mut table: toml::Table = s.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut config = Config::default();
+
+        if let Some(channels) = table.remove("channel") {
+            let channels = channels
+                .as_table()
+                .ok_or_else(|| String::from("channel must be a table of channels"))?;
+            for (name, section) in channels {
+                let section = section
+                    .as_table()
+                    .ok_or_else(|| format!("channel.\"{}\" must be a table", name))?;
+                let defaults =
+                    Defaults::parse(section).map_err(|e| format!("channel.\"{}\": {}", name, e))?;
+                config.channels.push((name.clone(), defaults));
+            }
+        }
+        config.global = Defaults::parse(&table)?;
+
+        Ok(config)
+    }
+
+    /// The defaults when searching `channel`, which like --channel may be
+    /// given with or without the leading `#`.
+    pub fn defaults_for(&self, channel: &str) -> Defaults {
+        let channel = channel.trim_start_matches('#');
+
+        self.channels
+            .iter()
+            .find(|(name, _)| name.trim_start_matches('#') == channel)
+            .map_or_else(|| self.global.clone(), |(_, d)| d.clone().or(&self.global))
+    }
+}
+
+/// Reads the config file given with --config, or the one in the default
+/// place if there is one there.
+pub fn load<F>(file: Option<&str>, env_var: F) -> Result<Config, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let path = match file {
+        Some(f) => path::PathBuf::from(f),
+        None => match default_path(env_var) {
+            Some(p) if p.is_file() => p,
+            _ => return Ok(Config::default()),
+        },
+    };
+
+    let contents = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Config::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Where the config file is looked for when --config isn't given.
+pub fn default_path<F>(env_var: F) -> Option<path::PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    Some(dir(env_var)?.join("config.toml"))
+}
+
+/// The directory with ircgrep's config file, where the state for
+/// --since-last is kept too.
+pub fn dir<F>(env_var: F) -> Option<path::PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let var = |v: &str| env_var(v).filter(|s| !s.is_empty());
+
+    let dir = match var("XDG_CONFIG_HOME") {
+        Some(x) => path::PathBuf::from(x),
+        None => path::Path::new(&var("HOME").or_else(|| var("USERPROFILE"))?).join(".config"),
+    };
+
+    Some(dir.join("ircgrep"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_sections() {
+        let config = Config::parse(
+            "network = \"libera\"\n\
+             strip_joins = false\n\
+             \n\
+             [channel.\"#rust\"]\n\
+             strip_joins = true\n\
+             \n\
+             [channel.\"##oftopic\"]\n\
+             network = \"oftc\"\n\
+             skip_blank = true\n",
+        )
+        .unwrap();
+
+        let rust = config.defaults_for("rust");
+        assert_eq!(rust.network.as_deref(), Some("libera"));
+        assert_eq!(rust.strip_joins, Some(true));
+        assert_eq!(config.defaults_for("#rust"), rust);
+
+        let oftopic = config.defaults_for("##oftopic");
+        assert_eq!(oftopic.network.as_deref(), Some("oftc"));
+        assert_eq!(oftopic.strip_joins, Some(false));
+        assert_eq!(oftopic.skip_blank, Some(true));
+
+        let git = config.defaults_for("#git");
+        assert_eq!(git.strip_joins, Some(false));
+        assert_eq!(git.skip_blank, None);
+
+        assert!(Config::parse("strip_joins = 1").is_err());
+        assert!(Config::parse("colour = true").is_err());
+        assert!(Config::parse("[channel.\"#git\"]\nnetwork = true").is_err());
+        assert!(Config::parse("channel = 1").is_err());
+        assert!(Config::parse("strip_joins = ").is_err());
+    }
+
+    #[test]
+    fn default_paths() {
+        let env = |v: &str| match v {
+            "HOME" => Some(String::from("/home/osse")),
+            _ => None,
+        };
+        assert_eq!(
+            default_path(env),
+            Some(path::PathBuf::from(
+                "/home/osse/.config/ircgrep/config.toml"
+            ))
+        );
+
+        let xdg = |v: &str| match v {
+            "HOME" => Some(String::from("/home/osse")),
+            "XDG_CONFIG_HOME" => Some(String::from("/etc/xdg")),
+            _ => None,
+        };
+        assert_eq!(
+            default_path(xdg),
+            Some(path::PathBuf::from("/etc/xdg/ircgrep/config.toml"))
+        );
+
+        assert_eq!(default_path(|_| None), None);
+    }
+}
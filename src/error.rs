@@ -0,0 +1,101 @@
+use std::fmt;
+use std::io;
+
+/// Why ircgrep gave up. Each kind of error has its own exit status, see
+/// `exit_code`.
+#[derive(Debug)]
+pub enum IrcgrepError {
+    /// Reading or writing failed, or --fail-fast stopped at a file that
+    /// couldn't be read.
+    IoError(io::Error),
+    /// The pattern, or the network or channel, isn't a valid regex.
+    RegexError(regex::Error),
+    /// The options given don't make sense, alone or together.
+    InvalidSettings(String),
+    /// There were no log files to search. The message says where they were
+    /// looked for.
+    NoFiles(String),
+    /// Whatever was reading the output stopped, as `head` does.
+    BrokenPipe,
+    /// An --exec command exited unsuccessfully. It has already been reported
+    /// for each file.
+    CommandFailed,
+}
+
+impl IrcgrepError {
+    /// The exit status for the error. Like grep's, 1 is for when nothing was
+    /// found to search and 2 is for trouble reading or writing. A broken pipe
+    /// isn't treated as an error, since the reader got all it wanted.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            IrcgrepError::BrokenPipe => 0,
+            IrcgrepError::RegexError(_)
+            | IrcgrepError::InvalidSettings(_)
+            | IrcgrepError::NoFiles(_) => 1,
+            IrcgrepError::IoError(_) | IrcgrepError::CommandFailed => 2,
+        }
+    }
+}
+
+impl fmt::Display for IrcgrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IrcgrepError::IoError(e) => write!(f, "{}", e),
+            IrcgrepError::RegexError(e) => write!(f, "{}", e),
+            IrcgrepError::InvalidSettings(s) | IrcgrepError::NoFiles(s) => write!(f, "{}", s),
+            IrcgrepError::BrokenPipe => write!(f, "broken pipe"),
+            IrcgrepError::CommandFailed => write!(f, "the --exec command failed"),
+        }
+    }
+}
+
+impl std::error::Error for IrcgrepError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IrcgrepError::IoError(e) => Some(e),
+            IrcgrepError::RegexError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IrcgrepError {
+    fn from(e: io::Error) -> IrcgrepError {
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            IrcgrepError::BrokenPipe
+        } else {
+            IrcgrepError::IoError(e)
+        }
+    }
+}
+
+impl From<regex::Error> for IrcgrepError {
+    fn from(e: regex::Error) -> IrcgrepError {
+        IrcgrepError::RegexError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let pipe = IrcgrepError::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(matches!(pipe, IrcgrepError::BrokenPipe));
+        assert_eq!(pipe.exit_code(), 0);
+
+        let denied = IrcgrepError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(matches!(denied, IrcgrepError::IoError(_)));
+        assert_eq!(denied.exit_code(), 2);
+
+        let pattern = String::from("(");
+        let regex = IrcgrepError::from(regex::Regex::new(&pattern).unwrap_err());
+        assert_eq!(regex.exit_code(), 1);
+        assert!(std::error::Error::source(&regex).is_some());
+
+        let invalid = IrcgrepError::InvalidSettings(String::from("--or needs --nickname"));
+        assert_eq!(invalid.to_string(), "--or needs --nickname");
+        assert_eq!(invalid.exit_code(), 1);
+    }
+}
@@ -0,0 +1,93 @@
+use regex::Regex;
+
+/// Separate patterns for the nick and the message, given on the command line
+/// as `nick:/alice/ msg:/hi/`. Either part can be left out. A `/` inside a
+/// pattern is written `\/`.
+#[derive(Debug, Default)]
+pub struct FieldRegex {
+    pub nick: Option<Regex>,
+    pub message: Option<Regex>,
+}
+
+impl FieldRegex {
+    pub fn parse(s: &str) -> Result<FieldRegex, String> {
+        let mut fields = FieldRegex::default();
+        let mut rest = s.trim_start();
+
+        while !rest.is_empty() {
+            let (name, tail) = rest
+                .split_once(":/")
+                .ok_or_else(|| format!("expected field:/regex/, got '{}'", rest))?;
+            let (pattern, tail) =
+                split_pattern(tail).ok_or_else(|| format!("missing closing / after {}:", name))?;
+            let regex = Regex::new(&pattern).map_err(|e| e.to_string())?;
+
+            let field = match name {
+                "nick" => &mut fields.nick,
+                "msg" | "message" => &mut fields.message,
+                _ => return Err(format!("unknown field '{}', expected nick or msg", name)),
+            };
+            if field.is_some() {
+                return Err(format!("{} given more than once", name));
+            }
+            *field = Some(regex);
+
+            rest = tail.trim_start();
+        }
+
+        if fields.nick.is_none() && fields.message.is_none() {
+            return Err(String::from("no fields given"));
+        }
+
+        Ok(fields)
+    }
+
+    pub fn is_match(&self, nick: &str, message: &str) -> bool {
+        self.nick.as_ref().is_none_or(|r| r.is_match(nick))
+            && self.message.as_ref().is_none_or(|r| r.is_match(message))
+    }
+}
+
+/// Splits `s` at the first `/` that isn't escaped, returning the pattern
+/// before it with the escapes removed and what comes after it.
+fn split_pattern(s: &str) -> Option<(String, &str)> {
+    let mut pattern = String::new();
+    let mut chars = s.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '/' => return Some((pattern, &s[i + 1..])),
+            '\\' if s[i + 1..].starts_with('/') => {
+                chars.next();
+                pattern.push('/');
+            }
+            _ => pattern.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let f = FieldRegex::parse("nick:/^alice$/ msg:/hi there/").unwrap();
+        assert!(f.is_match("alice", "oh hi there"));
+        assert!(!f.is_match("malice", "oh hi there"));
+        assert!(!f.is_match("alice", "bye"));
+
+        let f = FieldRegex::parse("  message:/a\\/b/").unwrap();
+        assert!(f.nick.is_none());
+        assert!(f.is_match("anyone", "a/b"));
+
+        assert!(FieldRegex::parse("").is_err());
+        assert!(FieldRegex::parse("nick:alice").is_err());
+        assert!(FieldRegex::parse("nick:/alice").is_err());
+        assert!(FieldRegex::parse("host:/x/").is_err());
+        assert!(FieldRegex::parse("nick:/a/ nick:/b/").is_err());
+        assert!(FieldRegex::parse("nick:/(/").is_err());
+    }
+}
@@ -1,66 +1,420 @@
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Join,
+    Part,
+    Quit,
+}
+
+/// A copy of a line's fields that isn't tied to the buffer it was read into,
+/// for keeping matches around after reading has moved on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedLine {
+    pub timestamp: String,
+    pub nick: String,
+    pub message: String,
+}
+
 pub struct LineView<'a> {
     line: &'a str,
+    delimiter: char,
     first_tab: usize,
     second_tab: usize,
+    keep_prefix: bool,
 }
 
 impl<'a> LineView<'a> {
     pub fn message(&self) -> &str {
-        &self.line[self.second_tab + 1..]
+        &self.line[self.second_tab + self.delimiter.len_utf8()..]
     }
 
     pub fn nick(&self) -> &str {
-        let nick = &self.line[self.first_tab + 1..self.second_tab];
+        let nick = self.prefixed_nick();
+        if self.keep_prefix {
+            return nick;
+        }
         match nick.strip_prefix(&['@', '+'][..]) {
             Some(n) => n,
             None => nick,
         }
     }
 
+    /// The nick with the `@` or `+` in front of it that shows whether the
+    /// person is an op or has voice.
+    fn prefixed_nick(&self) -> &str {
+        // With weechat.look.prefix_align the nick may be padded with spaces.
+        // Nicks can't contain spaces so trimming them is safe.
+        self.line[self.first_tab + self.delimiter.len_utf8()..self.second_tab].trim()
+    }
+
+    /// The `@` of an op or the `+` of someone with voice, if the nick has
+    /// either.
+    pub fn nick_prefix(&self) -> Option<char> {
+        let nick = self.prefixed_nick();
+        let prefix = nick.chars().next().filter(|c| matches!(c, '@' | '+'))?;
+        if nick.len() > 1 {
+            Some(prefix)
+        } else {
+            None
+        }
+    }
+
     pub fn timestamp(&self) -> &str {
         &self.line[0..self.first_tab]
     }
 
+    // WeeChat marks the lines it writes itself with one of these prefixes
+    // (weechat.look.prefix_join and friends). Actions use ` *`, which isn't
+    // an event, and `==` isn't one of WeeChat's prefixes at all, so both are
+    // left to look like nicks.
+
+    /// Whether someone joined: `-->`.
     pub fn is_join(&self) -> bool {
-        let nick = self.nick();
-        nick == "<--" || nick == "--" || nick == "-->"
+        self.nick() == "-->"
+    }
+
+    /// Whether someone parted or quit: `<--`.
+    pub fn is_part(&self) -> bool {
+        self.nick() == "<--"
     }
 
-    pub fn new(line: &'a str) -> LineView<'a> {
-        let first_tab = line.find('\t').unwrap();
-        let second_tab = line.get(first_tab + 1..).unwrap().find('\t').unwrap() + first_tab + 1;
+    /// Whether the line is some other network event, like a nick change, a
+    /// mode change or the topic: `--`.
+    pub fn is_network(&self) -> bool {
+        self.nick() == "--"
+    }
+
+    /// Whether WeeChat reported an error: `=!=`.
+    pub fn is_error(&self) -> bool {
+        self.nick() == "=!="
+    }
+
+    /// Whether the line is a /me action, `*` followed by the nick and what
+    /// they did in the message.
+    pub fn is_action(&self) -> bool {
+        self.nick() == "*"
+    }
+
+    /// Who wrote the line: the nick, or for an action the nick at the start
+    /// of the message. Joins, parts and other lines from WeeChat itself have
+    /// no one.
+    pub fn speaker(&self) -> Option<&str> {
+        if self.is_system() {
+            None
+        } else if self.is_action() {
+            self.message().split_whitespace().next()
+        } else {
+            Some(self.nick())
+        }
+    }
+
+    /// Whether the line is a join, part, quit or other network event, which
+    /// is what --strip-joins strips.
+    pub fn is_event(&self) -> bool {
+        self.is_join() || self.is_part() || self.is_network()
+    }
+
+    /// Which kind of join, part or quit the line records, if any.
+    pub fn event(&self) -> Option<Event> {
+        match self.nick() {
+            "-->" => Some(Event::Join),
+            "<--" if self.message().contains(" has quit") => Some(Event::Quit),
+            "<--" => Some(Event::Part),
+            _ => None,
+        }
+    }
 
+    /// The `user@host` in a join, part or quit, with any `~` left in.
+    pub fn hostmask(&self) -> Option<&str> {
+        self.event()?;
+        let (_, rest) = self.message().split_once(" (")?;
+        let (mask, _) = rest.split_once(')')?;
+        if mask.contains('@') {
+            Some(mask)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the line was written by WeeChat or the server rather than a
+    /// person: joins and the like, errors (`=!=`) and lines with an empty
+    /// prefix such as parts of the MOTD.
+    pub fn is_system(&self) -> bool {
+        self.is_event() || self.is_error() || self.nick().is_empty()
+    }
+
+    /// Whether the message is empty or consists only of whitespace.
+    pub fn is_blank(&self) -> bool {
+        self.message().trim().is_empty()
+    }
+
+    pub fn to_owned(&self) -> OwnedLine {
+        OwnedLine {
+            timestamp: self.timestamp().to_string(),
+            nick: self.nick().to_string(),
+            message: self.message().to_string(),
+        }
+    }
+
+    pub fn delimiter(&self) -> char {
+        self.delimiter
+    }
+
+    /// Makes `nick` leave the `@` or `+` in front of the nick.
+    pub fn keeping_prefix(self) -> LineView<'a> {
         LineView {
+            keep_prefix: true,
+            ..self
+        }
+    }
+
+    pub fn new(line: &'a str) -> Option<LineView<'a>> {
+        LineView::with_delimiter(line, '\t')
+    }
+
+    /// Splits the line on `delimiter` instead of a tab. Returns `None` if the
+    /// line doesn't contain at least two of them.
+    pub fn with_delimiter(line: &'a str, delimiter: char) -> Option<LineView<'a>> {
+        let first_tab = line.find(delimiter)?;
+        let after = first_tab + delimiter.len_utf8();
+        let second_tab = line.get(after..)?.find(delimiter)? + after;
+
+        Some(LineView {
             line,
+            delimiter,
             first_tab,
             second_tab,
-        }
+            keep_prefix: false,
+        })
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
     #[test]
     fn basic_parsing() {
         let line = "2020-06-22 11:18:46	osse	check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo";
-        let lv = LineView::new(&line);
+        let lv = LineView::new(&line).unwrap();
 
         assert_eq!(lv.timestamp(), "2020-06-22 11:18:46");
         assert_eq!(lv.nick(), "osse");
-        assert_eq!(lv.is_join(), false);
+        assert!(!lv.is_event());
         assert_eq!(lv.message(), "check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo");
     }
 
     #[test]
     fn basic_parsing2() {
         let line = "2020-06-22 11:40:05	<--	roadie (~user@2a02:8108:ec0:1427:38ed:3aa7:170e:5e4e) has quit (Remote host closed the connection)";
-        let lv = LineView::new(&line);
+        let lv = LineView::new(&line).unwrap();
 
         assert_eq!(lv.timestamp(), "2020-06-22 11:40:05");
         assert_eq!(lv.nick(), "<--");
-        assert_eq!(lv.is_join(), true);
+        assert!(lv.is_part());
+        assert!(lv.is_event());
         assert_eq!(lv.message(), "roadie (~user@2a02:8108:ec0:1427:38ed:3aa7:170e:5e4e) has quit (Remote host closed the connection)");
     }
+
+    #[test]
+    fn owned_line() {
+        let line = String::from("2020-06-22 11:18:46	@osse	hi");
+        let owned = LineView::new(&line).unwrap().to_owned();
+        drop(line);
+
+        assert_eq!(
+            owned,
+            OwnedLine {
+                timestamp: String::from("2020-06-22 11:18:46"),
+                nick: String::from("osse"),
+                message: String::from("hi"),
+            }
+        );
+    }
+
+    #[test]
+    fn nick_prefix() {
+        let op = LineView::new("2020-06-22 11:18:46	@osse	hi").unwrap();
+        assert_eq!(op.nick(), "osse");
+        assert_eq!(op.nick_prefix(), Some('@'));
+        assert_eq!(op.keeping_prefix().nick(), "@osse");
+
+        let voiced = LineView::new("2020-06-22 11:18:46	 +roadie	hi").unwrap();
+        assert_eq!(voiced.nick_prefix(), Some('+'));
+        assert_eq!(voiced.keeping_prefix().nick(), "+roadie");
+
+        let lv = LineView::new("2020-06-22 11:18:46	alice	hi").unwrap();
+        assert_eq!(lv.nick_prefix(), None);
+        assert_eq!(lv.keeping_prefix().nick(), "alice");
+
+        let join = LineView::new("2020-06-22 11:18:46	-->	alice has joined").unwrap();
+        assert!(join.keeping_prefix().is_join());
+    }
+
+    #[test]
+    fn padded_nick() {
+        let lv = LineView::new("2020-06-22 11:18:46	    osse	hi").unwrap();
+        assert_eq!(lv.nick(), "osse");
+
+        let lv = LineView::new("2020-06-22 11:18:46	   @osse 	hi").unwrap();
+        assert_eq!(lv.nick(), "osse");
+
+        let lv = LineView::new("2020-06-22 11:18:46	     -->	roadie has joined").unwrap();
+        assert!(lv.is_join());
+
+        let lv = LineView::new("2020-06-22 11:18:46	  [osse]_	hi").unwrap();
+        assert_eq!(lv.nick(), "[osse]_");
+    }
+
+    #[test]
+    fn empty_message() {
+        let lv = LineView::new("2020-06-22 11:18:46	osse	").unwrap();
+
+        assert_eq!(lv.nick(), "osse");
+        assert_eq!(lv.message(), "");
+        assert!(lv.is_blank());
+
+        let lv = LineView::new("2020-06-22 11:18:46	osse	 \t ").unwrap();
+        assert_eq!(lv.message(), " \t ");
+        assert!(lv.is_blank());
+    }
+
+    #[test]
+    fn custom_delimiter() {
+        let line = "2020-06-22 11:18:46|@osse|a | in the message";
+        let lv = LineView::with_delimiter(line, '|').unwrap();
+
+        assert_eq!(lv.timestamp(), "2020-06-22 11:18:46");
+        assert_eq!(lv.nick(), "osse");
+        assert_eq!(lv.message(), "a | in the message");
+
+        let lv = LineView::with_delimiter("2020-06-22 11:18:46→osse→hi", '→').unwrap();
+        assert_eq!(lv.nick(), "osse");
+        assert_eq!(lv.message(), "hi");
+
+        assert!(LineView::with_delimiter(line, ',').is_none());
+        assert!(LineView::with_delimiter("2020-06-22 11:18:46|osse", '|').is_none());
+    }
+
+    #[test]
+    fn events() {
+        let lv =
+            LineView::new("2020-06-22 11:40:05	-->	roadie (~user@example.com) has joined #git")
+                .unwrap();
+        assert_eq!(lv.event(), Some(Event::Join));
+
+        let lv =
+            LineView::new("2020-06-22 11:40:05	<--	roadie (~user@example.com) has left #git (bye)")
+                .unwrap();
+        assert_eq!(lv.event(), Some(Event::Part));
+
+        let lv = LineView::new("2020-06-22 11:40:05	<--	roadie (~user@example.com) has quit (Ping timeout: 260 seconds)").unwrap();
+        assert_eq!(lv.event(), Some(Event::Quit));
+
+        let lv = LineView::new("2020-06-22 11:40:05	--	roadie is now known as roadie_").unwrap();
+        assert_eq!(lv.event(), None);
+
+        let lv = LineView::new("2020-06-22 11:40:05	osse	roadie has quit again").unwrap();
+        assert_eq!(lv.event(), None);
+    }
+
+    #[test]
+    fn hostmasks() {
+        let lv = LineView::new("2020-06-22 11:40:05	-->	roadie (~user@192.0.2.17) has joined #git")
+            .unwrap();
+        assert_eq!(lv.hostmask(), Some("~user@192.0.2.17"));
+
+        let lv = LineView::new("2020-06-22 11:40:05	<--	roadie (~user@2a02:8108:ec0:1427:38ed:3aa7:170e:5e4e) has quit (Remote host closed the connection)").unwrap();
+        assert_eq!(
+            lv.hostmask(),
+            Some("~user@2a02:8108:ec0:1427:38ed:3aa7:170e:5e4e")
+        );
+
+        let lv =
+            LineView::new("2020-06-22 11:40:05	<--	osse (osse@user/osse) has left #git (see you)")
+                .unwrap();
+        assert_eq!(lv.hostmask(), Some("osse@user/osse"));
+
+        let lv = LineView::new("2020-06-22 11:40:05	--	roadie is now known as roadie_").unwrap();
+        assert_eq!(lv.hostmask(), None);
+
+        let lv =
+            LineView::new("2020-06-22 11:40:05	osse	roadie (~user@192.0.2.17) has joined").unwrap();
+        assert_eq!(lv.hostmask(), None);
+    }
+
+    #[test]
+    fn markers() {
+        let kinds = |line| {
+            let lv = LineView::new(line).unwrap();
+            (
+                lv.is_join(),
+                lv.is_part(),
+                lv.is_network(),
+                lv.is_error(),
+                lv.is_event(),
+            )
+        };
+
+        assert_eq!(
+            kinds("2020-06-22 11:40:05	-->	roadie (~user@example.com) has joined #git"),
+            (true, false, false, false, true)
+        );
+        assert_eq!(
+            kinds("2020-06-22 11:40:05	<--	roadie (~user@example.com) has left #git"),
+            (false, true, false, false, true)
+        );
+        assert_eq!(
+            kinds("2020-06-22 11:40:05	<--	roadie (~user@example.com) has quit (bye)"),
+            (false, true, false, false, true)
+        );
+        assert_eq!(
+            kinds("2020-06-22 11:40:05	--	roadie is now known as roadie_"),
+            (false, false, true, false, true)
+        );
+        assert_eq!(
+            kinds("2020-06-22 11:40:05	=!=	irc: command \"foo\" not found"),
+            (false, false, false, true, false)
+        );
+        assert_eq!(
+            kinds("2020-06-22 11:40:05	 *	roadie waves"),
+            (false, false, false, false, false)
+        );
+        assert!(LineView::new("2020-06-22 11:40:05	 *	roadie waves")
+            .unwrap()
+            .is_action());
+        let speaker = |line| LineView::new(line).unwrap().speaker().map(String::from);
+        assert_eq!(
+            speaker("2020-06-22 11:40:05	 *	roadie waves"),
+            Some(String::from("roadie"))
+        );
+        assert_eq!(
+            speaker("2020-06-22 11:40:05	@osse	hi"),
+            Some(String::from("osse"))
+        );
+        assert_eq!(speaker("2020-06-22 11:40:05	-->	osse has joined"), None);
+        assert_eq!(
+            kinds("2020-06-22 11:40:05	==	roadie"),
+            (false, false, false, false, false)
+        );
+    }
+
+    #[test]
+    fn system_lines() {
+        let lines = [
+            "2020-06-22 11:40:05	-->	roadie (~user@example.com) has joined #git",
+            "2020-06-22 11:40:05	--	Mode #git [+o osse] by ChanServ",
+            "2020-06-22 11:40:05	--	Topic for #git is \"git\"",
+            "2020-06-22 11:40:05	--	roadie is now known as roadie_",
+            "2020-06-22 11:40:05	=!=	irc: command \"foo\" not found",
+            "2020-06-22 11:40:05		- Welcome to Libera Chat -",
+        ];
+
+        for line in &lines {
+            assert!(LineView::new(line).unwrap().is_system(), "{}", line);
+        }
+
+        let lv = LineView::new("2020-06-22 11:40:05	@osse	-- is a fine separator").unwrap();
+        assert!(!lv.is_system());
+    }
 }
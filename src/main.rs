@@ -1,344 +1,6073 @@
+mod bookmarks;
+mod config;
+mod error;
+mod field_regex;
 mod line_view;
+mod query;
+mod reader;
+mod tui;
 
-use line_view::LineView;
+use bookmarks::Bookmarks;
+use error::IrcgrepError;
+use field_regex::FieldRegex;
+use line_view::{Event, LineView};
+use query::CompiledQuery;
+use reader::{Encoding, LogReader};
 
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, Timelike};
 use circular_queue::CircularQueue;
 
 #[macro_use]
 extern crate clap;
 
-use colored::Colorize;
 use regex::Regex;
 
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::env;
 use std::ffi::OsStr;
 use std::fs;
-use std::io::{stdout, BufRead, BufReader, Write};
+use std::hash::{Hash, Hasher};
+use std::io::{stderr, stdin, stdout, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::iter::Peekable;
 use std::path;
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
 
 #[derive(Debug, Default)]
 struct Settings {
     nickname: String,
+    /// The nicks from --nick-file, any of which matches like --nickname.
+    nick_list: Option<HashSet<String>>,
+    mention: Option<String>,
+    keep_nick_prefix: bool,
+    /// The nick prefix, `@` or `+`, that --ops-only or --voiced-only asks for.
+    role: Option<char>,
     channel: String,
     network: String,
     pattern_string: String,
-    pattern: Option<Regex>,
+    /// The patterns after the first when -e is given more than once. A line
+    /// matches if any of them do.
+    extra_patterns: Vec<String>,
+    /// The patterns, compiled by validate_settings.
+    query: CompiledQuery,
     context: usize,
+    context_matches: bool,
+    max_context: usize,
     strip_joins: bool,
+    content_only: bool,
+    skip_blank: bool,
+    squeeze_blank: bool,
+    /// Only the first match in each file, for --first-only.
+    first_only: bool,
+    join_pastes: bool,
+    /// How many messages are searched together with --multiline, 0 when
+    /// each line is searched on its own.
+    multiline: usize,
     strip_time_stamps: bool,
     count: bool,
     fixed: bool,
+    line_buffered: bool,
+    text: bool,
+    encoding: Encoding,
+    buffer_size: Option<usize>,
+    delimiter: Option<char>,
+    /// What goes between the time stamp, nick and message in the output, and
+    /// after the file name with --count, instead of what the log uses.
+    output_delimiter: Option<String>,
+    color: bool,
+    theme: Theme,
+    path_style: PathStyle,
+    verbose: bool,
+    progress: bool,
+    churn: bool,
+    or: bool,
+    since: Option<String>,
+    until: Option<String>,
+    modified_since: Option<std::time::Duration>,
+    recursive: bool,
+    dedup_files: bool,
+    /// How many levels of directories -r reads, counting the log directory
+    /// itself. There's no limit if it's not given.
+    max_depth: Option<usize>,
+    group: bool,
+    group_separator: Option<String>,
+    interactive: bool,
+    dedup_window: usize,
+    list_files: bool,
+    null: bool,
+    quote: bool,
+    unique_nicks: bool,
+    list: bool,
+    nick_message: bool,
+    match_timestamp: bool,
+    invert: bool,
+    invert_nick: bool,
+    percent: bool,
+    count_matches: bool,
+    count_once: bool,
+    min_count: usize,
+    count_to_stderr: bool,
+    json: bool,
+    json_array: bool,
+    no_messages: bool,
+    fail_fast: bool,
+    /// How many files couldn't be read, for --fail-fast.
+    errors: Cell<usize>,
+    /// How many files the --exec command failed for, for the exit status.
+    failed_commands: Cell<usize>,
+    /// With --since-last, where the last run left off in each log.
+    since_last: Option<Bookmarks>,
+    no_update: bool,
+    matrix: bool,
+    summary_only: bool,
+    heatmap: Option<Heatmap>,
+    fields_string: Option<String>,
+    fields: Option<FieldRegex>,
+    exec: Option<String>,
+    exec_batch: bool,
+    with_filename: bool,
+    show_target: bool,
+    sort: Option<SortBy>,
+    highlight_nick: bool,
+    dim_context: bool,
+    byte_offset: bool,
+    fit: Option<Fit>,
+    snippet: Option<usize>,
+    file_markers: bool,
+    only_nicks: bool,
+    word: bool,
+    word_chars: String,
+    exclude_channels: Vec<String>,
+    exclude_networks: Vec<String>,
+    replace: Option<String>,
+    render_actions: bool,
+    width: usize,
+    match_prefix: String,
+    match_suffix: String,
+    host_string: Option<String>,
+    host: Option<Regex>,
+    /// The nicks --ignore-bots leaves out, compiled from `bot_string`.
+    bot_string: Option<String>,
+    bots: Option<Regex>,
 }
 
+/// The nicks taken to be bots by --ignore-bots unless --bot-pattern says
+/// otherwise, like `dogbot` or `NickServ`.
+const BOT_PATTERN: &str = "(?i)(bot|serv)$";
+
+/// The colors picked with --theme, as the parameters of the escape codes
+/// that turn them on. An empty one leaves the text as it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Theme {
+    matched: &'static str,
+    /// The colors for the strings after the first when --fixed is given
+    /// several, in turn, so that each string stands out from the others.
+    others: &'static [&'static str],
+    file: &'static str,
+    separator: &'static str,
+}
+
+const THEMES: &[(&str, Theme)] = &[
+    (
+        "default",
+        Theme {
+            matched: "1;31",
+            others: &["1;32", "1;34", "1;33", "1;36"],
+            file: "35",
+            separator: "36",
+        },
+    ),
+    (
+        "solarized",
+        Theme {
+            matched: "1;33",
+            others: &["1;36", "1;35", "1;32"],
+            file: "34",
+            separator: "32",
+        },
+    ),
+    // For terminals with few or no colors: matches in reverse video and file
+    // names in bold.
+    (
+        "mono",
+        Theme {
+            matched: "1;7",
+            others: &[],
+            file: "1",
+            separator: "",
+        },
+    ),
+];
+
+impl Theme {
+    fn named(name: &str) -> Option<Theme> {
+        THEMES.iter().find(|(n, _)| *n == name).map(|&(_, t)| t)
+    }
+
+    /// The color for matches of the `needle`th fixed string.
+    fn matched_color(&self, needle: usize) -> &'static str {
+        match needle.checked_sub(1) {
+            Some(i) if !self.others.is_empty() => self.others[i % self.others.len()],
+            _ => self.matched,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        THEMES[0].1
+    }
+}
+
+/// How --sort orders the output of --count.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SortBy {
+    Count,
+    Name,
+    Date,
+}
+
+/// How file names are shown, chosen with --path-style.
+#[derive(Clone, Debug, Default, PartialEq)]
+enum PathStyle {
+    /// Just the name of the file.
+    #[default]
+    Name,
+    Absolute,
+    /// Relative to this directory, which is the log directory.
+    Relative(path::PathBuf),
+}
+
+/// How --wrap and --truncate make long messages fit the terminal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fit {
+    Wrap,
+    Truncate,
+}
+
+/// How --heatmap prints the weekday by hour counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Heatmap {
+    Csv,
+    Ascii,
+}
+
+/// Byte ranges of matches within a string.
+type Ranges = Vec<(usize, usize)>;
+
 #[derive(Debug, PartialEq)]
 enum MatchType {
     Match(Vec<(usize, usize)>),
+    /// The line matches but there's nothing to highlight, either because
+    /// only the nick was asked for or because the pattern only matched the
+    /// empty string.
     MatchNick,
     NoMatch,
     Skip,
 }
 
+/// Whether the line is left out of the search altogether, as if it weren't in
+/// the log.
+fn skip_line(settings: &Settings, lv: &LineView) -> bool {
+    (settings.strip_joins && lv.is_event())
+        || (settings.content_only && lv.is_system())
+        || (settings.skip_blank && lv.is_blank())
+        || !in_range(settings, lv.timestamp())
+        || settings
+            .bots
+            .as_ref()
+            .is_some_and(|b| b.is_match(lv.nick()))
+}
+
 fn match_line(settings: &Settings, lv: &LineView) -> MatchType {
-    if settings.strip_joins && lv.is_join() {
+    if skip_line(settings, lv) {
         return MatchType::Skip;
     }
 
-    let nick = lv.nick();
+    if let Some(host) = &settings.host {
+        if !lv.hostmask().is_some_and(|h| host.is_match(h)) {
+            return MatchType::NoMatch;
+        }
+    }
 
-    if !settings.nickname.is_empty() && settings.nickname != nick {
-        return MatchType::NoMatch;
+    if let Some(fields) = &settings.fields {
+        if !fields.is_match(lv.nick(), lv.message()) {
+            return MatchType::NoMatch;
+        }
     }
 
-    if settings.pattern_string.is_empty() {
-        return MatchType::MatchNick;
+    if settings.role.is_some() && lv.nick_prefix() != settings.role {
+        return MatchType::NoMatch;
     }
 
-    let mut v = Vec::<(usize, usize)>::new();
+    let nick = lv.nick();
+
+    // Mentions are found in the message but highlighted along with the
+    // pattern's matches, so they have to be moved with --nick-message.
+    let offset = if settings.nick_message {
+        nick.len() + 2
+    } else {
+        0
+    };
+    let mentions = match &settings.mention {
+        Some(to) => {
+            let m = mention_ranges(to, lv.message());
+            if m.is_empty() {
+                return MatchType::NoMatch;
+            }
+            m.into_iter()
+                .map(|(s, e)| (s + offset, e + offset))
+                .collect()
+        }
+        None => Vec::new(),
+    };
 
-    if !settings.fixed {
-        for m in settings
-            .pattern
-            .as_ref()
-            .unwrap()
-            .captures_iter(lv.message())
-        {
-            let c = m.get(0).unwrap();
-            v.push((c.start(), c.end()));
+    let nick_matches =
+        filters_nick(settings) && is_wanted_nick(settings, nick) != settings.invert_nick;
+
+    // Without a pattern the nick alone decides, with --or too, and it's all
+    // there is to invert, so -v -n alice selects everyone but alice.
+    if settings.pattern_string.is_empty() && settings.extra_patterns.is_empty() {
+        if filters_nick(settings) && nick_matches == settings.invert {
+            return MatchType::NoMatch;
         }
+        return if mentions.is_empty() {
+            MatchType::MatchNick
+        } else {
+            MatchType::Match(mentions)
+        };
+    }
+
+    // Normally both the nickname and the pattern have to match, with --or
+    // either one will do.
+    if !settings.or && filters_nick(settings) && !nick_matches {
+        return MatchType::NoMatch;
+    }
+
+    let nick_message;
+    let haystack = if settings.match_timestamp {
+        lv.timestamp()
+    } else if settings.nick_message {
+        nick_message = format!("{}: {}", nick, lv.message());
+        nick_message.as_str()
     } else {
-        for (pos, m) in lv.message().match_indices(&settings.pattern_string) {
-            v.push((pos, pos + m.len()));
-        }
+        lv.message()
+    };
+
+    let found = settings.query.find(haystack);
+    let (mut v, empty_match) = (found.ranges, found.empty);
+
+    // An inverted line is selected because the pattern isn't in it, so there's
+    // nothing to highlight.
+    if settings.invert {
+        return if (v.is_empty() && !empty_match) || (settings.or && nick_matches) {
+            MatchType::MatchNick
+        } else {
+            MatchType::NoMatch
+        };
     }
 
     if !v.is_empty() {
-        MatchType::Match(v)
+        v.extend(mentions);
+        MatchType::Match(merge_ranges(v))
+    } else if !mentions.is_empty() && empty_match {
+        MatchType::Match(mentions)
+    } else if empty_match || (settings.or && nick_matches) {
+        MatchType::MatchNick
     } else {
         MatchType::NoMatch
     }
 }
 
-fn print_line(lv: &LineView, matches: &[(usize, usize)]) {
-    print!("{}\t{}\t", lv.timestamp(), lv.nick());
+/// Finds where `nick` is mentioned in a message for --to. Like nicks on IRC
+/// it's matched regardless of ASCII case, and only where it isn't part of a
+/// longer nick.
+fn mention_ranges(nick: &str, message: &str) -> Ranges {
+    let is_nick_char = |c: char| c.is_alphanumeric() || "-_[]\\`^{}|".contains(c);
+    let haystack = message.to_ascii_lowercase();
+
+    haystack
+        .match_indices(&nick.to_ascii_lowercase())
+        .map(|(s, m)| (s, s + m.len()))
+        .filter(|&(s, e)| {
+            !message[..s].chars().next_back().is_some_and(is_nick_char)
+                && !message[e..].chars().next().is_some_and(is_nick_char)
+        })
+        .collect()
+}
 
-    let msg = lv.message();
+/// Sorts ranges and joins the ones that overlap, so that they can be
+/// highlighted one after the other.
+fn merge_ranges(mut ranges: Ranges) -> Ranges {
+    ranges.sort_unstable();
 
-    for p in matches {
-        print!("{}", msg.get(0..p.0).unwrap());
-        print!("{}", msg.get(p.0..p.1).unwrap().red().bold());
+    let mut merged: Ranges = Vec::with_capacity(ranges.len());
+    for (s, e) in ranges {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+
+    merged
+}
+
+/// Compiles the pattern the way the settings ask for.
+fn compile_query(settings: &Settings) -> Result<CompiledQuery, regex::Error> {
+    let mut patterns = vec![settings.pattern_string.as_str()];
+    patterns.extend(settings.extra_patterns.iter().map(String::as_str));
+
+    let regex = if patterns.len() == 1 {
+        settings.pattern_string.clone()
+    } else {
+        let groups: Vec<String> = patterns.iter().map(|p| format!("(?:{})", p)).collect();
+        groups.join("|")
+    };
+
+    let query = if settings.fixed && patterns.len() > 1 {
+        CompiledQuery::fixed_set(&patterns)
+    } else if settings.fixed {
+        CompiledQuery::fixed(&settings.pattern_string)
+    } else if settings.multiline > 0 {
+        // Let `^` and `$` match at the start and end of each message and `.`
+        // match the newlines between them.
+        CompiledQuery::regex(&format!("(?ms){}", regex))?
+    } else {
+        CompiledQuery::regex(&regex)?
+    };
+
+    if settings.word {
+        query.whole_words(&settings.word_chars)
+    } else {
+        Ok(query)
+    }
+}
+
+/// Whether only some nicks are wanted, with --nickname or --nick-file.
+fn filters_nick(settings: &Settings) -> bool {
+    !settings.nickname.is_empty() || settings.nick_list.is_some()
+}
+
+/// Whether `nick` is the one given with --nickname or one of those in the
+/// --nick-file. An empty --nick-file matches no one.
+fn is_wanted_nick(settings: &Settings, nick: &str) -> bool {
+    !settings.nickname.is_empty() && nick == settings.nickname
+        || settings
+            .nick_list
+            .as_ref()
+            .is_some_and(|l| l.contains(nick))
+}
+
+/// The nicks in a --nick-file, one per line. Blank lines and lines starting
+/// with `#` are left out.
+fn parse_nick_list(s: &str) -> HashSet<String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Checks a time stamp against --since and --until. These are compared as
+/// strings against the start of the time stamp, which works because WeeChat's
+/// time stamps sort chronologically. `since` is inclusive and `until` is not.
+fn in_range(settings: &Settings, timestamp: &str) -> bool {
+    if let Some(since) = &settings.since {
+        if timestamp < since.as_str() {
+            return false;
+        }
     }
 
-    if let Some(last) = msg.get(matches.last().unwrap().1..) {
-        println!("{}", last);
+    if let Some(until) = &settings.until {
+        if timestamp >= until.as_str() {
+            return false;
+        }
     }
+
+    true
 }
 
-fn open_file(filename: &path::PathBuf) -> std::io::Lines<BufReader<std::fs::File>> {
-    let file = fs::File::open(&filename).expect("Could not open file");
+/// The first day (inclusive) and last day (exclusive) covered by --today,
+/// --yesterday and --last-week. The last week is the seven days ending today.
+fn relative_range(name: &str, today: NaiveDate) -> Option<(NaiveDate, Option<NaiveDate>)> {
+    let days = Duration::days;
 
-    BufReader::new(file).lines()
+    match name {
+        "today" => Some((today, None)),
+        "yesterday" => Some((today - days(1), Some(today))),
+        "last-week" => Some((today - days(6), None)),
+        _ => None,
+    }
 }
 
-fn process_file(
-    settings: &Settings,
-    filename: &path::PathBuf,
-    mut writer: impl Write,
-) -> std::io::Result<()> {
-    let mut print_after: i32 = 0;
-    let mut context = CircularQueue::with_capacity(settings.context);
+/// The range covered by --date, which is just that day.
+fn date_range(date: &str) -> Option<(NaiveDate, Option<NaiveDate>)> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    Some((day, day.succ_opt()))
+}
 
-    for line in open_file(&filename) {
-        if let Ok(l) = line {
-            let lv = LineView::new(&l);
+/// Highlights a matched part of a message, in the color for the `needle`th
+/// fixed string. Messages may carry escape codes of their own, and one of
+/// those in the middle of a match would end the highlighting early, so it's
+/// turned back on after each of them.
+fn highlight(span: &str, theme: &Theme, needle: usize) -> String {
+    style(span, &format!("\x1b[{}m", theme.matched_color(needle)))
+}
 
-            match match_line(&settings, &lv) {
-                MatchType::Match(m) => {
-                    for cl in context.iter() {
-                        writeln!(writer, "{}", cl)?;
-                    }
-                    context.clear();
-                    print_line(&lv, &m);
-                    print_after = settings.context as i32;
-                }
-                MatchType::MatchNick => {
-                    for cl in context.iter() {
-                        writeln!(writer, "{}", cl)?;
-                    }
-                    context.clear();
-                    writeln!(writer, "{}", &l)?;
-                    print_after = settings.context as i32;
-                }
-                MatchType::NoMatch => {
-                    if print_after > 0 {
-                        writeln!(writer, "{}", &l)?;
-                        print_after -= 1;
-                        if print_after == 0 {
-                            writeln!(writer, "--")?;
-                        }
-                    }
+/// Turns on the style given by the escape code `start` for `span`, the same
+/// way `highlight` does.
+fn style(span: &str, start: &str) -> String {
+    let reset = "\x1b[0m";
 
-                    context.push(l);
-                }
-                MatchType::Skip => continue,
+    let mut out = String::new();
+    let mut rest = span;
+
+    while !rest.is_empty() {
+        let (text, code, tail) = match rest.find("\x1b[") {
+            Some(i) => {
+                let code_len = rest[i + 2..]
+                    .find(|c: char| !c.is_ascii_digit() && c != ';')
+                    .map(|j| j + 3)
+                    .unwrap_or(rest.len() - i);
+                let (text, tail) = rest.split_at(i);
+                let (code, tail) = tail.split_at(code_len);
+                (text, code, tail)
             }
+            None => (rest, "", ""),
+        };
+
+        if !text.is_empty() {
+            out.push_str(start);
+            out.push_str(text);
+            out.push_str(reset);
         }
+        out.push_str(code);
+        rest = tail;
     }
 
-    Ok(())
+    out
 }
 
-fn process_file_count(
-    settings: &Settings,
-    filename: &path::PathBuf,
+/// Writes `text` with the given ranges highlighted, and wrapped in
+/// --match-prefix and --match-suffix so they show up without colors too.
+fn write_highlighted(
     mut writer: impl Write,
+    settings: &Settings,
+    text: &str,
+    matches: &[(usize, usize)],
 ) -> std::io::Result<()> {
-    let mut count = 0;
+    let mut pos = 0;
+
+    for p in matches {
+        write_dimmed(&mut writer, settings, text.get(pos..p.0).unwrap())?;
+        let m = text.get(p.0..p.1).unwrap();
+        write!(writer, "{}", settings.match_prefix)?;
+        if settings.color && !settings.dim_context {
+            let needle = settings.query.needle(m);
+            write!(writer, "{}", highlight(m, &settings.theme, needle))?;
+        } else {
+            write!(writer, "{}", m)?;
+        }
+        write!(writer, "{}", settings.match_suffix)?;
+        pos = p.1;
+    }
 
-    for line in open_file(&filename) {
-        if let Ok(l) = line {
-            let lv = LineView::new(&l);
+    write_dimmed(&mut writer, settings, text.get(pos..).unwrap())
+}
 
-            match match_line(&settings, &lv) {
-                MatchType::Match(v) => count += v.len(),
-                MatchType::MatchNick => count += 1,
-                _ => continue,
-            }
+/// Writes text that isn't part of a match, dimmed with --dim-context so the
+/// matches stand out without being highlighted.
+fn write_dimmed(mut writer: impl Write, settings: &Settings, text: &str) -> std::io::Result<()> {
+    if settings.color && settings.dim_context && !text.is_empty() {
+        write!(writer, "{}", style(text, "\x1b[2m"))
+    } else {
+        write!(writer, "{}", text)
+    }
+}
+
+/// Splits matches made against the `nick: message` string used by
+/// --nick-message into ranges within the nick and ranges within the
+/// message. A match spanning the `: ` in the middle ends up in both.
+fn split_nick_message(nick_len: usize, matches: &[(usize, usize)]) -> (Ranges, Ranges) {
+    let msg_start = nick_len + 2;
+    let mut nick = Vec::new();
+    let mut msg = Vec::new();
+
+    for &(s, e) in matches {
+        if s < nick_len {
+            nick.push((s, e.min(nick_len)));
+        }
+        if e > msg_start {
+            msg.push((s.max(msg_start) - msg_start, e - msg_start));
         }
     }
-    writeln!(
-        writer,
-        "{}{}{}",
-        filename.file_name().unwrap().to_str().unwrap().purple(),
-        ":".cyan(),
-        count
-    )?;
 
-    Ok(())
+    (nick, msg)
 }
 
-fn get_log_files(settings: &Settings) -> Vec<path::PathBuf> {
-    let logdir = env::var("HOME").expect("HOME not set??") + "/.weechat/logs";
-    let logpath = path::Path::new(&logdir);
+fn print_line(
+    mut writer: impl Write,
+    settings: &Settings,
+    lv: &LineView,
+    matches: &[(usize, usize)],
+) -> std::io::Result<()> {
+    let d = output_delimiter(settings, lv);
+    let stamp = timestamp_column(settings, lv);
+    if settings.match_timestamp {
+        write_highlighted(&mut writer, settings, lv.timestamp(), matches)?;
+        write_dimmed(&mut writer, settings, &d)?;
+    } else {
+        write_dimmed(&mut writer, settings, &stamp)?;
+    }
 
-    let file_pattern = format!(
-        "^irc\\.{}\\.#*{}\\.weechatlog$",
-        settings.network, settings.channel
-    );
-    let file_pattern = Regex::new(&file_pattern).expect("Invalid regex");
+    if let Some(replacement) = &settings.replace {
+        let message = replace_matches(settings, lv.message(), matches, replacement);
+        write_dimmed(&mut writer, settings, &format!("{}{}", lv.nick(), d))?;
+        return writeln!(writer, "{}", message);
+    }
 
-    let mut logfiles = logpath
-        .read_dir()
-        .expect("Invalid directory")
-        .into_iter()
-        .map(|e| e.unwrap().path())
-        .filter(|p| {
-            p.extension() == Some(&OsStr::new("weechatlog"))
-                && file_pattern.is_match(p.file_name().unwrap().to_str().unwrap())
-        })
-        .collect::<Vec<path::PathBuf>>();
+    let (nick, msg) = if settings.match_timestamp {
+        (Vec::new(), Vec::new())
+    } else if settings.nick_message {
+        split_nick_message(lv.nick().len(), matches)
+    } else {
+        (Vec::new(), matches.to_vec())
+    };
+
+    // The message of an action starts with the nick, so with --render-actions
+    // the `*` goes in front of it like in a chat window.
+    let head = if settings.render_actions && lv.is_action() {
+        write_dimmed(&mut writer, settings, "* ")?;
+        String::from("* ")
+    } else {
+        write_highlighted(&mut writer, settings, lv.nick(), &nick)?;
+        write_dimmed(&mut writer, settings, &d)?;
+        format!("{}{}", lv.nick(), d)
+    };
+
+    let (message, msg) = match settings.snippet {
+        Some(n) if !msg.is_empty() => {
+            let (message, msg) = snippet(lv.message(), &msg, n);
+            (Cow::Owned(message), msg)
+        }
+        _ => (Cow::Borrowed(lv.message()), msg),
+    };
 
-    logfiles.sort();
+    let indent = columns(&format!("{}{}", stamp, head));
+    write_message(&mut writer, settings, indent, &message, &msg)?;
 
-    logfiles
+    writeln!(writer)
 }
 
-fn validate_settings(settings: &mut Settings) {
-    if settings.count
-        && (settings.strip_joins || settings.strip_time_stamps || settings.context > 0)
-    {
-        eprintln!("Can't combine --count with options affecting output\n");
-        std::process::exit(1);
+/// Replaces each match in `text` for --replace. In regex mode `$1`, `${name}`
+/// and so on refer to the groups captured by that match. Only the output
+/// changes, never the log.
+fn replace_matches(
+    settings: &Settings,
+    text: &str,
+    matches: &[(usize, usize)],
+    replacement: &str,
+) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+
+    for &(s, e) in matches {
+        out.push_str(&text[pos..s]);
+        settings.query.expand(replacement, text, (s, e), &mut out);
+        pos = e;
     }
 
-    if settings.nickname.is_empty() && settings.pattern_string.is_empty() {
-        eprintln!("Must give either --pattern or --nickname\n");
-        std::process::exit(1);
+    out.push_str(&text[pos..]);
+    out
+}
+
+/// Cuts a message down to `context` characters on either side of each match
+/// for --snippet, with `…` where something was left out. Matches close enough
+/// for their snippets to meet share one.
+fn snippet(message: &str, matches: &[(usize, usize)], context: usize) -> (String, Ranges) {
+    let starts = message
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(message.len()))
+        .collect::<Vec<_>>();
+    let char_index = |byte| starts.binary_search(&byte).unwrap_or_else(|i| i);
+    let chars = starts.len() - 1;
+
+    let mut windows: Vec<(usize, usize, Ranges)> = Vec::new();
+    for &(s, e) in matches {
+        let first = char_index(s).saturating_sub(context);
+        let last = (char_index(e) + context).min(chars);
+        match windows.last_mut() {
+            Some(w) if first <= w.1 => {
+                w.1 = w.1.max(last);
+                w.2.push((s, e));
+            }
+            _ => windows.push((first, last, vec![(s, e)])),
+        }
     }
 
-    if !settings.fixed {
-        settings.pattern = Some(Regex::new(&settings.pattern_string).expect("Invalid regex"));
+    let mut out = String::new();
+    let mut ranges = Vec::new();
+    let mut end = 0;
+    for (first, last, matches) in windows {
+        let start = starts[first];
+        end = starts[last];
+        if start > 0 {
+            out.push('…');
+        }
+        for (s, e) in matches {
+            ranges.push((s - start + out.len(), e - start + out.len()));
+        }
+        out.push_str(&message[start..end]);
+    }
+    if end < message.len() {
+        out.push('…');
     }
+
+    (out, ranges)
 }
 
-fn main() -> Result<(), std::io::Error> {
-    let mut settings = Settings {
-        channel: String::from(".*"),
-        network: String::from(".*"),
-        ..Default::default()
-    };
+/// Prints a line that matched on the nick alone with the nick highlighted,
+/// for --highlight-nick.
+fn print_nick_line(
+    mut writer: impl Write,
+    settings: &Settings,
+    lv: &LineView,
+) -> std::io::Result<()> {
+    let d = output_delimiter(settings, lv);
+    let stamp = timestamp_column(settings, lv);
+    write_dimmed(&mut writer, settings, &stamp)?;
+    write_highlighted(&mut writer, settings, lv.nick(), &[(0, lv.nick().len())])?;
+    write_dimmed(&mut writer, settings, &d)?;
 
-    let matches = clap_app!(ircgrep =>
-        (version: "0.1.0")
-        (author: "Øystein Walle <oystwa@gmail.com>")
-        (@arg NICKNAME: -n --nickname +takes_value "nickname")
-        (@arg CHANNEL:  -c --channel  +takes_value "channel")
-        (@arg PATTERN:  -e --pattern  +takes_value default_value(".*") "nickname")
-        (@arg NETWORK:  -N --network  +takes_value default_value(".*") "network")
-        (@arg FIXED:    -f --fixed                 "fixed string search")
-        (@arg STRIP_TS: -d --("strip-timestamps")  "strip time stamps")
-        (@arg STRIP_J:  -j --("strip-joins")       "strip joins/leaves and whatnot")
-        (@arg CONTEXT:  -C --context  +takes_value "context lines")
-        (@arg COUNT:    -t --count                 "count")
-    )
-    .get_matches();
+    let indent = columns(&format!("{}{}{}", stamp, lv.nick(), d));
+    write_message(&mut writer, settings, indent, lv.message(), &[])?;
 
-    if let Some(n) = matches.value_of("NICKNAME") {
-        settings.nickname = n.to_string();
-    }
-    if let Some(c) = matches.value_of("CHANNEL") {
-        settings.channel = c.to_string();
+    writeln!(writer)
+}
+
+/// The time stamp and the delimiter after it, or nothing with
+/// --strip-timestamps.
+fn timestamp_column(settings: &Settings, lv: &LineView) -> String {
+    if settings.strip_time_stamps {
+        String::new()
+    } else {
+        format!("{}{}", lv.timestamp(), output_delimiter(settings, lv))
     }
-    if let Some(p) = matches.value_of("PATTERN") {
-        settings.pattern_string = p.to_string();
+}
+
+/// What to print between the columns: --output-delimiter, or else whatever
+/// the log itself uses.
+fn output_delimiter(settings: &Settings, lv: &LineView) -> String {
+    match &settings.output_delimiter {
+        Some(d) => d.clone(),
+        None => lv.delimiter().to_string(),
     }
-    if let Some(n) = matches.value_of("NETWORK") {
-        settings.network = n.to_string();
+}
+
+/// Prints a line without highlighting anything. It's printed as is unless
+/// it has to be wrapped or truncated or have its time stamp stripped.
+fn print_plain(mut writer: impl Write, settings: &Settings, line: &str) -> std::io::Result<()> {
+    match parse_line(settings, line) {
+        Some(lv)
+            if settings.fit.is_some()
+                || settings.output_delimiter.is_some()
+                || settings.strip_time_stamps
+                || settings.render_actions && lv.is_action() =>
+        {
+            print_line(writer, settings, &lv, &[])
+        }
+        _ => {
+            write_dimmed(&mut writer, settings, line)?;
+            writeln!(writer)
+        }
     }
-    settings.fixed = matches.is_present("FIXED");
-    settings.strip_time_stamps = matches.is_present("STRIP_TS");
-    settings.strip_joins = matches.is_present("STRIP_J");
-    settings.context = match matches.value_of("CONTEXT") {
-        Some(c) => c.parse::<usize>().expect("a number"),
-        None => 0,
-    };
-    settings.count = matches.is_present("COUNT");
+}
+
+/// How many columns `s` takes up on a terminal with tab stops every eight
+/// columns.
+fn columns(s: &str) -> usize {
+    s.chars().fold(0, |col, c| match c {
+        '\t' => (col / 8 + 1) * 8,
+        _ => col + 1,
+    })
+}
 
-    validate_settings(&mut settings);
+/// Writes a message that starts `indent` columns in, wrapped or truncated to
+/// the terminal width with --wrap and --truncate.
+fn write_message(
+    mut writer: impl Write,
+    settings: &Settings,
+    indent: usize,
+    message: &str,
+    matches: &[(usize, usize)],
+) -> std::io::Result<()> {
+    let fit = match settings.fit {
+        Some(fit) => fit,
+        None => return write_highlighted(writer, settings, message, matches),
+    };
 
-    let files = get_log_files(&settings);
+    // Leave some room for the message even if the nick takes up most of the
+    // line.
+    let width = settings.width.saturating_sub(indent).max(20);
 
-    if !settings.count {
-        for f in files {
-            process_file(&settings, &f, &mut stdout())?;
+    for (i, (text, ranges, cut)) in fit_message(message, matches, width, fit).iter().enumerate() {
+        if i > 0 {
+            write!(writer, "\n{:1$}", "", indent)?;
         }
-    } else {
-        for f in files {
-            process_file_count(&settings, &f, &mut stdout())?;
+        write_highlighted(&mut writer, settings, text, ranges)?;
+        if *cut {
+            write!(writer, "…")?;
         }
     }
 
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Splits a message into pieces of at most `width` characters, with the
+/// matches moved to fit. With `Fit::Truncate` only the first piece is kept,
+/// one character shorter to make room for an ellipsis, and it's flagged as
+/// cut off.
+fn fit_message<'a>(
+    message: &'a str,
+    matches: &[(usize, usize)],
+    width: usize,
+    fit: Fit,
+) -> Vec<(&'a str, Ranges, bool)> {
+    let mut bounds = vec![0];
+    let mut cut = false;
 
-    #[test]
-    fn test_match_line() {
-        let mut settings = Settings::default();
-        settings.nickname = String::from("osse");
-        settings.fixed = true;
-        settings.pattern_string = String::from("diagnosing");
+    if fit == Fit::Truncate {
+        if message.chars().count() > width {
+            bounds.extend(message.char_indices().nth(width - 1).map(|(i, _)| i));
+            cut = true;
+        }
+    } else {
+        bounds.extend(
+            message
+                .char_indices()
+                .skip(width)
+                .step_by(width)
+                .map(|(i, _)| i),
+        );
+    }
+    if !cut {
+        bounds.push(message.len());
+    }
 
-        let line = "2020-06-22 11:18:46	osse	check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo";
-        let lv = LineView::new(&line);
+    bounds
+        .windows(2)
+        .map(|w| {
+            let (start, end) = (w[0], w[1]);
+            let ranges = matches
+                .iter()
+                .filter(|&&(s, e)| s < end && e > start)
+                .map(|&(s, e)| (s.max(start) - start, e.min(end) - start))
+                .collect();
+            (&message[start..end], ranges, cut)
+        })
+        .collect()
+}
 
-        let m = match_line(&settings, &lv);
+fn parse_line<'a>(settings: &Settings, line: &'a str) -> Option<LineView<'a>> {
+    let lv = match settings.delimiter {
+        Some(d) => LineView::with_delimiter(line, d),
+        None => LineView::new(line),
+    }?;
 
-        let v = vec![(20, 30)];
-        assert_eq!(m, MatchType::Match(v));
+    if settings.keep_nick_prefix {
+        Some(lv.keeping_prefix())
+    } else {
+        Some(lv)
     }
+}
 
-    #[test]
-    fn test_no_match_line() {
-        let mut settings = Settings::default();
-        settings.nickname = String::from("foo");
-        settings.fixed = true;
-        settings.pattern_string = String::from("diagnosing");
+/// Prints a warning about a file that couldn't be read, unless asked not to
+/// with --no-messages.
+fn warn(settings: &Settings, path: &path::Path, e: &std::io::Error) {
+    settings.errors.set(settings.errors.get() + 1);
+    if !settings.no_messages {
+        eprintln!("ircgrep: {}: {}", path.display(), e);
+    }
+}
 
-        let line = "2020-06-22 11:18:46	osse	check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo";
-        let lv = LineView::new(&line);
+/// Reads the lines of a log file. A file that can't be opened is warned about
+/// and treated as empty so that the rest still get searched, and one that
+/// can't be read to the end is warned about and cut short.
+fn open_file<'a>(
+    settings: &'a Settings,
+    filename: &'a path::PathBuf,
+) -> impl Iterator<Item = String> + 'a {
+    open_lines(settings, filename).map(|(_, line)| line)
+}
 
-        let m = match_line(&settings, &lv);
+/// Like `open_file` but gives the byte offset in the file where each line
+/// starts along with it, for --byte-offset.
+fn open_lines<'a>(
+    settings: &'a Settings,
+    filename: &'a path::PathBuf,
+) -> impl Iterator<Item = (u64, String)> + 'a {
+    let file = match fs::File::open(filename) {
+        Ok(f) => Some(f),
+        Err(e) => {
+            warn(settings, filename, &e);
+            None
+        }
+    };
 
-        assert_eq!(m, MatchType::NoMatch);
-    }
+    let lines = file
+        .map(|f| match settings.buffer_size {
+            Some(n) => LogReader::with_capacity(n, f, settings.text),
+            None => LogReader::new(f, settings.text),
+        })
+        .map(|r| r.with_encoding(settings.encoding).with_offsets())
+        .into_iter()
+        .flatten()
+        .map_while(move |r| r.map_err(|e| warn(settings, filename, &e)).ok())
+        .filter(move |(_, l)| is_new(settings, filename, l));
 
-    #[test]
-    fn test_match_line_many_matches() {
-        let mut settings = Settings::default();
-        settings.nickname = String::from("osse");
-        settings.fixed = true;
-        settings.pattern_string = String::from("re");
+    JoinPastes::new(settings, lines)
+}
 
-        let line = "2020-06-22 11:18:46	osse	check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo";
-        let lv = LineView::new(&line);
+/// Whether a line is one --since-last hasn't seen before. Without it, or if
+/// the line has no time stamp, every line is.
+fn is_new(settings: &Settings, filename: &path::Path, line: &str) -> bool {
+    let bookmarks = match &settings.since_last {
+        Some(b) => b,
+        None => return true,
+    };
+    parse_line(settings, line).is_none_or(|lv| bookmarks.is_new(filename, lv.timestamp()))
+}
 
-        let m = match_line(&settings, &lv);
+/// Joins the lines of a paste into one for --join-pastes. Lines are taken to
+/// be part of the same paste when they're from the same nick and each comes
+/// at most a second after the one before it. The messages are joined with
+/// spaces. Lines from WeeChat itself, like joins, are never joined.
+struct JoinPastes<I: Iterator<Item = (u64, String)>> {
+    lines: Peekable<I>,
+    enabled: bool,
+    delimiter: char,
+}
 
-        let v = vec![(10, 12), (39, 41), (61, 63), (90, 92)];
-        assert_eq!(m, MatchType::Match(v));
+impl<I: Iterator<Item = (u64, String)>> JoinPastes<I> {
+    fn new(settings: &Settings, lines: I) -> JoinPastes<I> {
+        JoinPastes {
+            lines: lines.peekable(),
+            enabled: settings.join_pastes,
+            delimiter: settings.delimiter.unwrap_or('\t'),
+        }
     }
+}
 
-    #[test]
-    fn test_context() {
-        let mut settings = Settings::default();
-        settings.nickname = String::from("osse");
-        settings.fixed = true;
-        settings.pattern_string = String::from("re");
-        settings.context = 3;
+/// The nick and time of a line that might be part of a paste.
+fn paste_key(line: &str, delimiter: char) -> Option<(String, NaiveDateTime)> {
+    let lv = LineView::with_delimiter(line, delimiter)?;
+    if lv.is_system() || lv.is_action() {
+        return None;
+    }
+    let time = NaiveDateTime::parse_from_str(lv.timestamp(), "%Y-%m-%d %H:%M:%S").ok()?;
+    Some((lv.nick().to_string(), time))
+}
+
+impl<I: Iterator<Item = (u64, String)>> Iterator for JoinPastes<I> {
+    type Item = (u64, String);
+
+    /// A joined paste starts where its first line does.
+    fn next(&mut self) -> Option<(u64, String)> {
+        let (offset, mut line) = self.lines.next()?;
+        if !self.enabled {
+            return Some((offset, line));
+        }
+
+        let (nick, mut time) = match paste_key(&line, self.delimiter) {
+            Some(k) => k,
+            None => return Some((offset, line)),
+        };
+
+        while let Some((_, next)) = self.lines.peek() {
+            let (next_nick, next_time) = match paste_key(next, self.delimiter) {
+                Some(k) => k,
+                None => break,
+            };
+            let gap = next_time - time;
+            if next_nick != nick || gap < Duration::zero() || gap > Duration::seconds(1) {
+                break;
+            }
+
+            let (_, next) = self.lines.next().unwrap();
+            let lv = LineView::with_delimiter(&next, self.delimiter).unwrap();
+            line.push(' ');
+            line.push_str(lv.message());
+            time = next_time;
+        }
+
+        Some((offset, line))
+    }
+}
+
+/// Remembers the last few matched lines for --dedup so that repeats of them
+/// can be dropped.
+struct Dedup {
+    recent: CircularQueue<u64>,
+}
+
+impl Dedup {
+    fn new(settings: &Settings) -> Dedup {
+        Dedup {
+            recent: CircularQueue::with_capacity(settings.dedup_window),
+        }
+    }
+
+    /// Turns a match into a `NoMatch` if the same nick said the same thing
+    /// within the window.
+    fn filter(&mut self, lv: &LineView, m: MatchType) -> MatchType {
+        if self.recent.capacity() == 0 {
+            return m;
+        }
+
+        match m {
+            MatchType::Match(_) | MatchType::MatchNick => {
+                let mut hasher = DefaultHasher::new();
+                (lv.nick(), lv.message()).hash(&mut hasher);
+                let h = hasher.finish();
+
+                let seen = self.recent.iter().any(|&r| r == h);
+                self.recent.push(h);
+
+                if seen {
+                    MatchType::NoMatch
+                } else {
+                    m
+                }
+            }
+            _ => m,
+        }
+    }
+}
+
+fn process_file(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    if settings.multiline > 0 {
+        return process_file_multiline(settings, filename, writer);
+    }
+
+    let mut matched = 0;
+    let mut print_after = 0;
+    let mut context: CircularQueue<(usize, u64, String)> =
+        CircularQueue::with_capacity(settings.context);
+    let mut last_printed: Option<usize> = None;
+    let mut dedup = Dedup::new(settings);
+    let mut n = 0;
+    let mut prev_blank = false;
+
+    // Like grep, matches are followed by a `:` and context lines by a `-`.
+    let prefix = line_prefix(settings, filename);
+    let write_prefix = |writer: &mut dyn Write, offset: u64, sep| -> std::io::Result<()> {
+        if let Some(p) = &prefix {
+            write!(writer, "{}{}", p, sep)?;
+        }
+        if settings.byte_offset {
+            write!(writer, "{}{}", offset, sep)?;
+        }
+        Ok(())
+    };
+
+    for (offset, l) in open_lines(settings, filename) {
+        let lv = match parse_line(settings, &l) {
+            Some(lv) => lv,
+            None => continue,
+        };
+
+        let mut m = dedup.filter(&lv, match_line(settings, &lv));
+        if m == MatchType::Skip {
+            continue;
+        }
+        // With --first-only later matches can only be context for the first.
+        if settings.first_only && matched > 0 {
+            m = MatchType::NoMatch;
+        }
+        // With --context-matches the context is what people said, so
+        // joins and such are passed over as if they weren't there.
+        if settings.context_matches && m == MatchType::NoMatch && lv.is_system() {
+            continue;
+        }
+        n += 1;
+
+        match m {
+            MatchType::Match(_) | MatchType::MatchNick => {
+                // Like grep, separate groups of lines that aren't next to
+                // each other, and let groups that overlap run together.
+                let first = context.asc_iter().next().map_or(n, |&(i, _, _)| i);
+                if settings.context > 0 && last_printed.is_some_and(|p| first > p + 1) {
+                    writeln!(writer, "--")?;
+                    prev_blank = false;
+                }
+
+                if settings.file_markers && last_printed.is_none() {
+                    let first_line = context
+                        .asc_iter()
+                        .next()
+                        .and_then(|(_, _, cl)| parse_line(settings, cl));
+                    let timestamp = first_line.as_ref().unwrap_or(&lv).timestamp();
+                    write_file_marker(&mut writer, filename, timestamp)?;
+                }
+
+                for (_, co, cl) in context.asc_iter() {
+                    if parse_line(settings, cl)
+                        .is_some_and(|clv| squeeze_blank(settings, &clv, &mut prev_blank))
+                    {
+                        continue;
+                    }
+                    write_prefix(&mut writer, *co, '-')?;
+                    print_plain(&mut writer, settings, cl)?;
+                }
+                context.clear();
+
+                if squeeze_blank(settings, &lv, &mut prev_blank) {
+                    // Still a match, just not worth printing again.
+                } else if let MatchType::Match(v) = m {
+                    write_prefix(&mut writer, offset, ':')?;
+                    print_line(&mut writer, settings, &lv, &v)?;
+                } else if settings.highlight_nick
+                    && !settings.invert_nick
+                    && is_wanted_nick(settings, lv.nick())
+                {
+                    write_prefix(&mut writer, offset, ':')?;
+                    print_nick_line(&mut writer, settings, &lv)?;
+                } else {
+                    write_prefix(&mut writer, offset, ':')?;
+                    print_plain(&mut writer, settings, &l)?;
+                }
+                matched += 1;
+                last_printed = Some(n);
+                print_after = settings.context;
+            }
+            _ if print_after > 0 => {
+                if !squeeze_blank(settings, &lv, &mut prev_blank) {
+                    write_prefix(&mut writer, offset, '-')?;
+                    print_plain(&mut writer, settings, &l)?;
+                }
+                last_printed = Some(n);
+                print_after -= 1;
+            }
+            _ => {
+                context.push((n, offset, l));
+            }
+        }
+
+        if settings.line_buffered {
+            writer.flush()?;
+        }
+
+        if settings.first_only && matched > 0 && print_after == 0 {
+            break;
+        }
+    }
+
+    Ok(matched)
+}
+
+/// Whether --squeeze-blank leaves out a line because it's blank and so was
+/// the line printed before it. `prev_blank` is updated for the next line.
+fn squeeze_blank(settings: &Settings, lv: &LineView, prev_blank: &mut bool) -> bool {
+    if !settings.squeeze_blank {
+        return false;
+    }
+
+    let blank = lv.is_blank();
+    let squeeze = blank && *prev_blank;
+    *prev_blank = blank;
+    squeeze
+}
+
+/// Like `process_file` but for --multiline, where the pattern is searched for
+/// in the next few messages joined by newlines. A match is reported at the
+/// line it starts in, followed by the lines it carries on into.
+///
+/// Every line is part of several windows, so this is slower than searching
+/// line by line, more so the larger the window.
+fn process_file_multiline(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let query = &settings.query;
+    let prefix = line_prefix(settings, filename);
+    let write_prefix = |writer: &mut dyn Write, sep| match &prefix {
+        Some(p) => write!(writer, "{}{}", p, sep),
+        None => Ok(()),
+    };
+
+    let mut lines = open_file(settings, filename)
+        .filter(|l| parse_line(settings, l).is_some_and(|lv| !skip_line(settings, &lv)));
+    let mut window: VecDeque<String> = lines.by_ref().take(settings.multiline).collect();
+    let mut matched = 0;
+    let mut n = 0;
+    // Line numbers counted from the start of the window.
+    let mut last_printed: Option<usize> = None;
+
+    while !window.is_empty() {
+        n += 1;
+        let views: Vec<LineView> = window
+            .iter()
+            .filter_map(|l| parse_line(settings, l))
+            .collect();
+        let first = &views[0];
+
+        if !filters_nick(settings) || is_wanted_nick(settings, first.nick()) {
+            let text = views
+                .iter()
+                .map(|lv| lv.message())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let found: Ranges = query
+                .find(&text)
+                .ranges
+                .into_iter()
+                .filter(|&(s, _)| s <= first.message().len())
+                .collect();
+
+            if let Some(end) = found.iter().map(|&(_, e)| e).max() {
+                if last_printed.is_some_and(|p| n > p + 1) {
+                    writeln!(writer, "--")?;
+                }
+                if settings.file_markers && last_printed.is_none() {
+                    write_file_marker(&mut writer, filename, first.timestamp())?;
+                }
+
+                let mut offset = 0;
+                for (i, lv) in views.iter().enumerate() {
+                    if i > 0 && offset >= end {
+                        break;
+                    }
+                    let len = lv.message().len();
+                    if last_printed.is_none_or(|p| n + i > p) {
+                        let ranges: Ranges = found
+                            .iter()
+                            .map(|&(s, e)| (s.max(offset), e.min(offset + len)))
+                            .filter(|&(s, e)| s < e)
+                            .map(|(s, e)| (s - offset, e - offset))
+                            .collect();
+                        write_prefix(&mut writer, if i == 0 { ':' } else { '-' })?;
+                        print_line(&mut writer, settings, lv, &ranges)?;
+                        last_printed = Some(n + i);
+                    }
+                    offset += len + 1;
+                }
+                matched += 1;
+                if settings.first_only {
+                    break;
+                }
+            }
+        }
+
+        window.pop_front();
+        window.extend(lines.next());
+
+        if settings.line_buffered {
+            writer.flush()?;
+        }
+    }
+
+    Ok(matched)
+}
+
+/// Shows where the output from one file begins for --file-markers, with the
+/// date of the first line printed from it.
+fn write_file_marker(
+    mut writer: impl Write,
+    filename: &path::Path,
+    timestamp: &str,
+) -> std::io::Result<()> {
+    let name = filename.file_name().unwrap().to_string_lossy();
+    let name = name.strip_suffix(".weechatlog").unwrap_or(&name);
+    let date = timestamp.split(' ').next().unwrap_or("");
+    writeln!(writer, "--- {} [{}] ---", name, date)
+}
+
+/// Calls `f` with every line in the file that matches, for the modes that
+/// aggregate matches rather than print them. Returns how many lines were read.
+fn for_each_match<F>(settings: &Settings, filename: &path::PathBuf, mut f: F) -> usize
+where
+    F: FnMut(&LineView, MatchType),
+{
+    let mut dedup = Dedup::new(settings);
+    let mut total = 0;
+
+    for l in open_file(settings, filename) {
+        total += 1;
+        let lv = match parse_line(settings, &l) {
+            Some(lv) => lv,
+            None => continue,
+        };
+
+        match dedup.filter(&lv, match_line(settings, &lv)) {
+            m @ MatchType::Match(_) | m @ MatchType::MatchNick => f(&lv, m),
+            _ => continue,
+        }
+
+        if settings.first_only {
+            break;
+        }
+    }
+
+    total
+}
+
+/// How many times a line matched, which is how many ranges were found in it,
+/// for --count-matches and --json's `match_count`. A line selected with
+/// nothing to highlight, on the nick alone or with -v, counts as one match
+/// even though it has no ranges.
+fn match_count(m: &MatchType) -> usize {
+    match m {
+        MatchType::Match(v) => v.len(),
+        _ => 1,
+    }
+}
+
+/// Describes matches for --json by both byte and character offsets, since
+/// they differ as soon as there's anything but ASCII in the text.
+fn json_ranges(text: &str, ranges: &[(usize, usize)]) -> serde_json::Value {
+    let char_offset = |byte| text.char_indices().take_while(|&(i, _)| i < byte).count();
+
+    ranges
+        .iter()
+        .map(|&(s, e)| {
+            serde_json::json!({
+                "byte_range": [s, e],
+                "char_range": [char_offset(s), char_offset(e)],
+            })
+        })
+        .collect()
+}
+
+/// Describes a match for --json and --json-array.
+fn json_match(
+    filename: &path::Path,
+    target: Option<&(String, String)>,
+    settings: &Settings,
+    lv: &LineView,
+    m: &MatchType,
+) -> serde_json::Value {
+    let (timestamp_matches, nick_matches, message_matches) = match m {
+        MatchType::Match(v) if settings.match_timestamp => (v.clone(), Vec::new(), Vec::new()),
+        MatchType::Match(v) if settings.nick_message => {
+            let (nick, message) = split_nick_message(lv.nick().len(), v);
+            (Vec::new(), nick, message)
+        }
+        MatchType::Match(v) => (Vec::new(), Vec::new(), v.clone()),
+        _ => (Vec::new(), Vec::new(), Vec::new()),
+    };
+
+    serde_json::json!({
+        "file": display_name(settings, filename),
+        "network": target.map(|t| &t.0),
+        "channel": target.map(|t| &t.1),
+        "timestamp": lv.timestamp(),
+        "nick": lv.nick(),
+        "message": lv.message(),
+        "timestamp_matches": json_ranges(lv.timestamp(), &timestamp_matches),
+        "nick_matches": json_ranges(lv.nick(), &nick_matches),
+        "matches": json_ranges(lv.message(), &message_matches),
+        "match_count": match_count(m),
+    })
+}
+
+/// Prints each match as a JSON object on a line of its own.
+fn process_file_json(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let mut matched = 0;
+    let mut result = Ok(());
+    let target = file_target(filename);
+
+    for_each_match(settings, filename, |lv, m| {
+        if result.is_ok() {
+            matched += 1;
+            let v = json_match(filename, target.as_ref(), settings, lv, &m);
+            result = writeln!(writer, "{}", v);
+        }
+    });
+
+    result.map(|_| matched)
+}
+
+/// Prints all the matches as one JSON array for --json-array, ending with an
+/// object like `{"summary": {"files": 3, "matches": 42}}`. The matches are
+/// written as they're found rather than collected first.
+fn json_array(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let mut matched = 0;
+    let mut result = write!(writer, "[");
+
+    for f in files {
+        let target = file_target(f);
+        for_each_match(settings, f, |lv, m| {
+            if result.is_ok() {
+                matched += 1;
+                let v = json_match(f, target.as_ref(), settings, lv, &m);
+                result = write!(writer, "{},", v);
+            }
+        });
+    }
+    result?;
+
+    let summary = serde_json::json!({
+        "summary": {
+            "files": files.len(),
+            "matches": matched,
+        }
+    });
+    writeln!(writer, "{}]", summary)?;
+
+    Ok(matched)
+}
+
+/// Formats `part` as a percentage of `total` with one decimal.
+fn percentage(part: usize, total: usize) -> String {
+    if total == 0 {
+        return String::from("0.0%");
+    }
+
+    format!("{:.1}%", part as f64 * 100.0 / total as f64)
+}
+
+/// The numbers --count reports for a file.
+struct FileCount {
+    count: usize,
+    lines: usize,
+    total: usize,
+}
+
+fn count_file(settings: &Settings, filename: &path::PathBuf) -> FileCount {
+    let mut lines = 0;
+    let mut occurrences = 0;
+
+    let total = for_each_match(settings, filename, |_, m| {
+        lines += 1;
+        occurrences += match_count(&m);
+    });
+    let count = if settings.count_matches && !settings.count_once {
+        occurrences
+    } else {
+        lines
+    };
+
+    FileCount {
+        count,
+        lines,
+        total,
+    }
+}
+
+/// Writes the file name and a `:` in front of the numbers printed for it by
+/// --count and --churn.
+fn write_file_label(
+    mut writer: impl Write,
+    settings: &Settings,
+    filename: &path::Path,
+) -> std::io::Result<()> {
+    let name = display_name(settings, filename);
+    let sep = settings.output_delimiter.as_deref().unwrap_or(":");
+    if settings.color {
+        let paint = |text: &str, sgr: &str| match sgr {
+            "" => text.to_string(),
+            _ => format!("\x1b[{}m{}\x1b[0m", sgr, text),
+        };
+        write!(
+            writer,
+            "{}{}",
+            paint(&name, settings.theme.file),
+            paint(sep, settings.theme.separator)
+        )
+    } else {
+        write!(writer, "{}{}", name, sep)
+    }
+}
+
+/// Prints the count for a file, unless it's below --min-count. Hidden counts
+/// still go towards the number of matches --verbose reports.
+fn write_count(
+    mut writer: impl Write,
+    settings: &Settings,
+    filename: &path::Path,
+    c: &FileCount,
+) -> std::io::Result<()> {
+    if c.count < settings.min_count {
+        return Ok(());
+    }
+
+    write_file_label(&mut writer, settings, filename)?;
+    write!(writer, "{}", c.count)?;
+    if settings.percent {
+        write!(writer, " ({})", percentage(c.lines, c.total))?;
+    }
+    writeln!(writer)
+}
+
+fn process_file_count(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    writer: impl Write,
+) -> std::io::Result<usize> {
+    let c = count_file(settings, filename);
+    write_count(writer, settings, filename, &c)?;
+    Ok(c.count)
+}
+
+/// Prints the counts for all the files at once, ordered by --sort. Files are
+/// in date order to begin with, and the sorts are stable, so ties stay that
+/// way.
+fn sorted_counts(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    by: SortBy,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let mut found = 0;
+    let mut counts = files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            if settings.progress {
+                show_progress(settings, i, files.len(), f, found);
+            }
+            let c = count_file(settings, f);
+            found += c.count;
+            (f, c)
+        })
+        .collect::<Vec<_>>();
+    if settings.progress {
+        eprint!("\r\x1b[K");
+    }
+
+    match by {
+        SortBy::Count => counts.sort_by_key(|(_, c)| std::cmp::Reverse(c.count)),
+        SortBy::Name => counts.sort_by_key(|(f, _)| f.file_name().map(OsStr::to_os_string)),
+        SortBy::Date => {}
+    }
+
+    for (f, c) in &counts {
+        write_count(&mut writer, settings, f, c)?;
+    }
+
+    Ok(counts.iter().map(|(_, c)| c.count).sum())
+}
+
+/// Prints the path of the file if anything in it matches. The path is
+/// terminated by a NUL instead of a newline with --null, for `xargs -0`.
+fn process_file_list(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let found = open_file(settings, filename).any(|l| match parse_line(settings, &l) {
+        Some(lv) => matches!(
+            match_line(settings, &lv),
+            MatchType::Match(_) | MatchType::MatchNick
+        ),
+        None => false,
+    });
+
+    if !found {
+        return Ok(0);
+    }
+
+    let end = if settings.null { '\0' } else { '\n' };
+    let name = filename.to_string_lossy();
+    if settings.quote {
+        write!(writer, "{}{}", shell_quote(&name), end)?;
+    } else {
+        write!(writer, "{}{}", name, end)?;
+    }
+
+    Ok(1)
+}
+
+/// Quotes a path for --quote so that a POSIX shell reads it back as it is.
+/// Paths made of only safe characters are left alone.
+fn shell_quote(s: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+
+    if !s.is_empty() && s.chars().all(safe) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("'{}'", s.replace('\'', "'\\''")))
+    }
+}
+
+/// Like `process_file` but with a header naming the file before its matches.
+/// The output is held back until the file is done so that files without any
+/// matches don't get a header.
+fn process_file_grouped(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    printed: bool,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let mut buf = Vec::new();
+    let matched = process_file(settings, filename, &mut buf)?;
+
+    if matched > 0 {
+        if let (true, Some(sep)) = (printed, &settings.group_separator) {
+            writeln!(writer, "{}", sep)?;
+        }
+        writeln!(writer, "==> {} <==", display_name(settings, filename))?;
+        writer.write_all(&buf)?;
+    }
+
+    Ok(matched)
+}
+
+/// Gathers up every matching line for --interactive.
+fn collect_hits(settings: &Settings, files: &[path::PathBuf]) -> Vec<tui::Hit> {
+    let mut hits = Vec::new();
+
+    for f in files {
+        let mut dedup = Dedup::new(settings);
+
+        for (offset, l) in open_lines(settings, f) {
+            let lv = match parse_line(settings, &l) {
+                Some(lv) => lv,
+                None => continue,
+            };
+
+            let matches = match dedup.filter(&lv, match_line(settings, &lv)) {
+                MatchType::Match(m) => m,
+                MatchType::MatchNick => Vec::new(),
+                _ => continue,
+            };
+
+            let (timestamp_matches, nick_matches, message_matches) = if settings.match_timestamp {
+                (matches, Vec::new(), Vec::new())
+            } else if settings.nick_message {
+                let (nick, message) = split_nick_message(lv.nick().len(), &matches);
+                (Vec::new(), nick, message)
+            } else {
+                (Vec::new(), Vec::new(), matches)
+            };
+
+            hits.push(tui::Hit {
+                file: f.clone(),
+                offset,
+                line: lv.to_owned(),
+                timestamp_matches,
+                nick_matches,
+                message_matches,
+            });
+
+            if settings.first_only {
+                break;
+            }
+        }
+    }
+
+    hits
+}
+
+/// Everyone who said something matching, in sorted order.
+fn matching_nicks(settings: &Settings, files: &[path::PathBuf]) -> BTreeSet<String> {
+    let mut nicks = BTreeSet::new();
+
+    for f in files {
+        for_each_match(settings, f, |lv, _| match lv.speaker() {
+            Some(n) if !nicks.contains(n) => {
+                nicks.insert(n.to_string());
+            }
+            _ => {}
+        });
+    }
+
+    nicks
+}
+
+/// Finds everyone who said something matching. With --list the nicks are
+/// printed in sorted order after the number of them.
+fn unique_nicks(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let nicks = matching_nicks(settings, files);
+
+    writeln!(writer, "{}", nicks.len())?;
+    if settings.list {
+        for n in &nicks {
+            writeln!(writer, "{}", n)?;
+        }
+    }
+
+    Ok(nicks.len())
+}
+
+/// Prints one line for --summary-only instead of the matches, counts or
+/// tables: how many matches there were, from how many nicks, and the first
+/// and last day they're from.
+fn summary(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let mut matched = 0;
+    let mut nicks = BTreeSet::new();
+    let mut days: Option<(String, String)> = None;
+
+    for f in files {
+        for_each_match(settings, f, |lv, m| {
+            matched += if settings.count_matches && !settings.count_once {
+                match_count(&m)
+            } else {
+                1
+            };
+            if let Some(n) = lv.speaker() {
+                nicks.insert(n.to_string());
+            }
+
+            let day = lv.timestamp().get(..10).unwrap_or_else(|| lv.timestamp());
+            match &mut days {
+                Some((first, last)) => {
+                    if day < first.as_str() {
+                        *first = day.to_string();
+                    }
+                    if day > last.as_str() {
+                        *last = day.to_string();
+                    }
+                }
+                None => days = Some((day.to_string(), day.to_string())),
+            }
+        });
+    }
+
+    write!(writer, "{} matches from {} nicks", matched, nicks.len())?;
+    if let Some((first, last)) = days {
+        write!(writer, ", {} to {}", first, last)?;
+    }
+    writeln!(writer)?;
+
+    Ok(matched)
+}
+
+/// Prints everyone who said something matching, one per line, for
+/// --only-nicks. Actions count for the nick doing them, and joins, parts and
+/// the like aren't anyone's.
+fn only_nicks(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let nicks = matching_nicks(settings, files);
+
+    for n in &nicks {
+        writeln!(writer, "{}", n)?;
+    }
+
+    Ok(nicks.len())
+}
+
+/// Counts matching lines per nick and day for --matrix, printed as CSV with a
+/// row for each day and a column for each nick.
+fn matrix(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    // Joins and such have no nick to put them under.
+    let (cells, total) = tally(settings, files, |lv| {
+        let day = lv.timestamp().split(' ').next().unwrap_or("");
+        Some((day.to_string(), lv.speaker()?.to_string()))
+    });
+    let days = cells.keys().map(|(d, _)| d).collect::<BTreeSet<_>>();
+    let nicks = cells.keys().map(|(_, n)| n).collect::<BTreeSet<_>>();
+
+    write!(writer, "date")?;
+    for n in &nicks {
+        write!(writer, ",{}", csv_field(n))?;
+    }
+    writeln!(writer)?;
+
+    for d in &days {
+        write!(writer, "{}", d)?;
+        for n in &nicks {
+            let count = cells.get(&(d.to_string(), n.to_string())).unwrap_or(&0);
+            write!(writer, ",{}", count)?;
+        }
+        writeln!(writer)?;
+    }
+
+    Ok(total)
+}
+
+/// Counts the matching lines in `files` by whatever `key` puts them in,
+/// for --matrix and --heatmap. Lines `key` returns `None` for are left out,
+/// though they still count towards the total that's returned.
+fn tally<K: Ord>(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    mut key: impl FnMut(&LineView) -> Option<K>,
+) -> (BTreeMap<K, usize>, usize) {
+    let mut cells = BTreeMap::new();
+    let mut total = 0;
+
+    for f in files {
+        for_each_match(settings, f, |lv, _| {
+            if let Some(k) = key(lv) {
+                *cells.entry(k).or_insert(0) += 1;
+            }
+            total += 1;
+        });
+    }
+
+    (cells, total)
+}
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Prints the number of matching lines for each hour of each weekday, with
+/// Monday first. Lines whose time stamp can't be parsed are left out.
+fn heatmap(
+    settings: &Settings,
+    files: &[path::PathBuf],
+    format: Heatmap,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let (cells, total) = tally(settings, files, |lv| {
+        let t = NaiveDateTime::parse_from_str(lv.timestamp(), "%Y-%m-%d %H:%M:%S").ok()?;
+        Some((
+            t.weekday().num_days_from_monday() as usize,
+            t.hour() as usize,
+        ))
+    });
+
+    let mut grid = [[0; 24]; 7];
+    for ((day, hour), count) in cells {
+        grid[day][hour] = count;
+    }
+
+    match format {
+        Heatmap::Csv => {
+            write!(writer, "day")?;
+            for hour in 0..24 {
+                write!(writer, ",{}", hour)?;
+            }
+            writeln!(writer)?;
+
+            for (day, row) in WEEKDAYS.iter().zip(&grid) {
+                write!(writer, "{}", day)?;
+                for count in row {
+                    write!(writer, ",{}", count)?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        Heatmap::Ascii => {
+            let mut header = String::from("    ");
+            for hour in (0..24).step_by(6) {
+                header.push_str(&format!("{:<6}", hour));
+            }
+            writeln!(writer, "{}", header.trim_end())?;
+
+            let max = grid.iter().flatten().copied().max().unwrap_or(0);
+            for (day, row) in WEEKDAYS.iter().zip(&grid) {
+                let cells = row.iter().map(|&c| shade(c, max)).collect::<String>();
+                writeln!(writer, "{} {}", day, cells)?;
+            }
+        }
+    }
+
+    Ok(total)
+}
+
+/// Picks a character for a cell of the ASCII heatmap, darker the closer
+/// `count` is to `max`. Only empty cells are left blank.
+fn shade(count: usize, max: usize) -> char {
+    const SHADES: &[u8] = b" .:-=+*#%@";
+
+    if count == 0 {
+        return ' ';
+    }
+    let i = 1 + (count * (SHADES.len() - 1) - 1) / max.max(count);
+    SHADES[i] as char
+}
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(s: &str) -> Cow<'_, str> {
+    if s.contains(&[',', '"', '\n'][..]) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// Tallies joins, parts and quits in a file. Unlike the other modes this
+/// looks at every line, so the nickname and pattern don't apply.
+fn process_file_churn(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    mut writer: impl Write,
+) -> std::io::Result<usize> {
+    let (mut joins, mut parts, mut quits) = (0, 0, 0);
+
+    for l in open_file(settings, filename) {
+        let lv = match parse_line(settings, &l) {
+            Some(lv) => lv,
+            None => continue,
+        };
+
+        match lv.event() {
+            Some(Event::Join) => joins += 1,
+            Some(Event::Part) => parts += 1,
+            Some(Event::Quit) => quits += 1,
+            None => continue,
+        }
+    }
+
+    write_file_label(&mut writer, settings, filename)?;
+    writeln!(writer, "joins={} parts={} quits={}", joins, parts, quits)?;
+
+    Ok(joins + parts + quits)
+}
+
+/// Splits an --exec command into words the way a shell would, though only
+/// its quoting is understood: single quotes keep everything up to the next one
+/// as is, and a backslash keeps the next character as is, except inside
+/// double quotes where it only does so for `"`, `\\`, `$` and `\``.
+fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    // The word so far, if one has started. `''` is an empty word.
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.push(c),
+                        None => return Err(format!("unmatched ' in {}", command)),
+                    }
+                }
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => w.push(c),
+                            Some(c) => {
+                                w.push('\\');
+                                w.push(c);
+                            }
+                            None => return Err(format!("unmatched \" in {}", command)),
+                        },
+                        Some(c) => w.push(c),
+                        None => return Err(format!("unmatched \" in {}", command)),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(format!("{} ends in a backslash", command)),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// Builds the arguments for --exec. Each `{}` is replaced by the matching
+/// lines, and if there isn't one the lines are given on stdin instead.
+fn exec_args(words: Vec<String>, lines: &[String]) -> (Vec<String>, Option<String>) {
+    let mut args = Vec::new();
+    let mut substituted = false;
+
+    for a in words {
+        if a == "{}" {
+            args.extend(lines.iter().cloned());
+            substituted = true;
+        } else {
+            args.push(a);
+        }
+    }
+
+    if substituted {
+        (args, None)
+    } else {
+        let mut stdin = lines.join("\n");
+        stdin.push('\n');
+        (args, Some(stdin))
+    }
+}
+
+/// Runs the --exec command for some matching lines. Returns whether it
+/// exited successfully.
+fn run_command(command: &str, lines: &[String]) -> std::io::Result<bool> {
+    let words = split_command(command)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let (args, stdin) = exec_args(words, lines);
+    let (program, args) = match args.split_first() {
+        Some(a) => a,
+        None => return Ok(true),
+    };
+
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    if stdin.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| std::io::Error::new(e.kind(), format!("couldn't run {}: {}", program, e)))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        // The command may not care about its input and exit early, which is
+        // fine.
+        let _ = pipe.write_all(input.as_bytes());
+    }
+
+    Ok(child.wait()?.success())
+}
+
+/// Runs a command for every matching line, or once with all of a file's
+/// matching lines with --exec-batch.
+fn process_file_exec(settings: &Settings, filename: &path::PathBuf) -> std::io::Result<usize> {
+    let command = settings.exec.as_deref().unwrap_or("");
+    let mut matched = 0;
+    let mut batch = Vec::new();
+    let mut result = Ok(true);
+
+    for_each_match(settings, filename, |lv, _| {
+        let d = lv.delimiter();
+        let line = format!("{}{}{}{}{}", lv.timestamp(), d, lv.nick(), d, lv.message());
+        matched += 1;
+
+        if settings.exec_batch {
+            batch.push(line);
+        } else if let Ok(ok) = result {
+            result = run_command(command, &[line]).map(|r| ok && r);
+        }
+    });
+
+    if settings.exec_batch && !batch.is_empty() {
+        result = run_command(command, &batch);
+    }
+
+    if !result? {
+        settings
+            .failed_commands
+            .set(settings.failed_commands.get() + 1);
+        if !settings.no_messages {
+            eprintln!("ircgrep: {} failed", command);
+        }
+    }
+
+    Ok(matched)
+}
+
+/// Searches a file in whichever way the settings ask for. `printed` says
+/// whether earlier files had any matches, for the separator between groups.
+fn search_file(
+    settings: &Settings,
+    filename: &path::PathBuf,
+    printed: bool,
+    writer: impl Write,
+) -> std::io::Result<usize> {
+    if settings.exec.is_some() {
+        process_file_exec(settings, filename)
+    } else if settings.churn {
+        process_file_churn(settings, filename, writer)
+    } else if settings.count {
+        process_file_count(settings, filename, writer)
+    } else if settings.list_files {
+        process_file_list(settings, filename, writer)
+    } else if settings.json {
+        process_file_json(settings, filename, writer)
+    } else if settings.group {
+        process_file_grouped(settings, filename, printed, writer)
+    } else {
+        process_file(settings, filename, writer)
+    }
+}
+
+/// Shows which file is being searched on stderr, overwriting the previous
+/// report. With --count it also shows how many matches the files before it
+/// had.
+fn show_progress(settings: &Settings, i: usize, total: usize, filename: &path::Path, found: usize) {
+    let name = filename.file_name().unwrap().to_string_lossy();
+    if settings.count {
+        eprint!("\r[{}/{}] {} matches, {}\x1b[K", i + 1, total, found, name);
+    } else {
+        eprint!("\r[{}/{}] {}\x1b[K", i + 1, total, name);
+    }
+}
+
+fn display_name<'a>(settings: &Settings, filename: &'a path::Path) -> Cow<'a, str> {
+    match &settings.path_style {
+        PathStyle::Name => filename.file_name().unwrap().to_string_lossy(),
+        PathStyle::Absolute => match path::absolute(filename) {
+            Ok(p) => Cow::Owned(p.to_string_lossy().into_owned()),
+            Err(_) => filename.to_string_lossy(),
+        },
+        // Files from --files-from may be anywhere, and are left as they are
+        // if they aren't in the log directory.
+        PathStyle::Relative(base) => filename
+            .strip_prefix(base)
+            .unwrap_or(filename)
+            .to_string_lossy(),
+    }
+}
+
+/// What to put in front of each line with -H: the file name, or the network
+/// and channel with --show-target.
+fn line_prefix(settings: &Settings, filename: &path::Path) -> Option<String> {
+    if settings.show_target {
+        if let Some((network, channel)) = file_target(filename) {
+            return Some(format!("{} {}", network, channel));
+        }
+    }
+
+    if settings.with_filename || settings.show_target {
+        Some(display_name(settings, filename).into_owned())
+    } else {
+        None
+    }
+}
+
+/// The places WeeChat may keep its logs, most specific first: `$WEECHAT_HOME`,
+/// the XDG data directory used by WeeChat 3.2 and later, and finally the
+/// legacy `~/.weechat`. The home directory is `HOME` on Unix-likes and
+/// `USERPROFILE` on Windows.
+fn log_dir_candidates<F>(env_var: F) -> Vec<path::PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let var = |v: &str| env_var(v).filter(|s| !s.is_empty());
+    let home = var("HOME").or_else(|| var("USERPROFILE"));
+
+    let mut candidates = Vec::new();
+
+    if let Some(w) = var("WEECHAT_HOME") {
+        candidates.push(path::Path::new(&w).join("logs"));
+    }
+
+    if let Some(x) = var("XDG_DATA_HOME") {
+        candidates.push(path::Path::new(&x).join("weechat").join("logs"));
+    } else if let Some(h) = &home {
+        candidates.push(
+            path::Path::new(h)
+                .join(".local")
+                .join("share")
+                .join("weechat")
+                .join("logs"),
+        );
+    }
+
+    if let Some(h) = &home {
+        candidates.push(path::Path::new(h).join(".weechat").join("logs"));
+    }
+
+    candidates
+}
+
+/// Works out where the logs live. An explicit directory always wins,
+/// otherwise the first candidate that exists is used. If none of them exist
+/// the last one is returned so that errors point somewhere sensible.
+fn resolve_log_dir<F>(log_dir: Option<&str>, env_var: F) -> Option<path::PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(d) = log_dir {
+        return Some(path::PathBuf::from(d));
+    }
+
+    let candidates = log_dir_candidates(env_var);
+
+    candidates
+        .iter()
+        .find(|c| c.is_dir())
+        .or_else(|| candidates.last())
+        .cloned()
+}
+
+/// Lists the log files in `logpath` that should be searched, in the order
+/// they get searched. `now` is what --modified-since counts back from.
+fn get_log_files(
+    settings: &Settings,
+    logpath: &path::Path,
+    now: SystemTime,
+) -> Result<Vec<path::PathBuf>, IrcgrepError> {
+    let file_pattern = log_file_pattern(&settings.network, &settings.channel)?;
+    let excluded = excluded_files(settings)?;
+
+    let depth = if settings.recursive {
+        settings.max_depth.unwrap_or(usize::MAX)
+    } else {
+        1
+    };
+    let mut entries = Vec::new();
+    list_dir(settings, logpath, depth, &mut entries);
+
+    let cutoff = settings.modified_since.and_then(|d| now.checked_sub(d));
+    let date = log_file_date();
+
+    let mut logfiles = entries
+        .into_iter()
+        .filter(|p| {
+            let name = p.file_name().unwrap().to_str().unwrap();
+            p.extension() == Some(OsStr::new("weechatlog"))
+                && file_pattern.is_match(name)
+                && !excluded.iter().any(|r| r.is_match(name))
+        })
+        .filter(|p| cutoff.is_none_or(|c| modified_after(p, c)))
+        .filter(|p| {
+            let name = p.file_name().and_then(OsStr::to_str).unwrap_or("");
+            date.captures(name)
+                .is_none_or(|c| date_in_range(settings, &c[1]))
+        })
+        .collect::<Vec<path::PathBuf>>();
+
+    sort_log_files(&mut logfiles);
+
+    Ok(logfiles)
+}
+
+/// Adds the paths in `dir` to `paths`, and with a `depth` above 1 the paths
+/// in its subdirectories as well, `depth - 1` levels down. Symlinks to
+/// directories aren't followed so that a loop can't go on forever.
+fn list_dir(settings: &Settings, dir: &path::Path, depth: usize, paths: &mut Vec<path::PathBuf>) {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn(settings, dir, &e);
+            return;
+        }
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        if depth > 1 && entry.file_type().is_ok_and(|t| t.is_dir()) {
+            list_dir(settings, &entry.path(), depth - 1, paths);
+        } else {
+            paths.push(entry.path());
+        }
+    }
+}
+
+/// Reads the files to search for --files-from, one path per line, from `list`
+/// or from stdin if it's `-`. Paths that can't be found are warned about and
+/// left out.
+fn files_from(settings: &Settings, list: &str) -> Vec<path::PathBuf> {
+    if list == "-" {
+        return read_file_list(settings, stdin().lock());
+    }
+
+    match fs::File::open(list) {
+        Ok(f) => read_file_list(settings, BufReader::new(f)),
+        Err(e) => {
+            warn(settings, path::Path::new(list), &e);
+            Vec::new()
+        }
+    }
+}
+
+fn read_file_list(settings: &Settings, reader: impl BufRead) -> Vec<path::PathBuf> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| path::PathBuf::from(l.trim_end_matches('\r')))
+        .filter(|p| match fs::metadata(p) {
+            Ok(_) => true,
+            Err(e) => {
+                warn(settings, p, &e);
+                false
+            }
+        })
+        .collect()
+}
+
+/// Whether the file was modified at or after `cutoff`, for --modified-since.
+/// Files whose modification time can't be read are kept so that opening them
+/// reports the problem.
+fn modified_after(path: &path::Path, cutoff: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_or(true, |t| t >= cutoff)
+}
+
+/// Leaves out files that have already been listed, whether under the same
+/// path, another path through a symlink or another hard link. With
+/// --dedup-files copies with the same contents are left out too, which
+/// means reading every file an extra time.
+fn unique_files(settings: &Settings, files: Vec<path::PathBuf>) -> Vec<path::PathBuf> {
+    let mut seen = BTreeSet::new();
+    let mut contents = BTreeSet::new();
+
+    files
+        .into_iter()
+        .filter(|f| file_id(f).is_none_or(|id| seen.insert(id)))
+        .filter(|f| !settings.dedup_files || content_hash(f).is_none_or(|h| contents.insert(h)))
+        .collect()
+}
+
+/// What identifies a file no matter which path leads to it.
+#[cfg(unix)]
+fn file_id(path: &path::Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &path::Path) -> Option<path::PathBuf> {
+    fs::canonicalize(path).ok()
+}
+
+/// The length of the file and a hash of what's in it, for --dedup-files.
+fn content_hash(path: &path::Path) -> Option<(u64, u64)> {
+    let mut reader = BufReader::new(fs::File::open(path).ok()?);
+    let mut hasher = DefaultHasher::new();
+    let mut len = 0;
+
+    loop {
+        let buf = reader.fill_buf().ok()?;
+        if buf.is_empty() {
+            break;
+        }
+        hasher.write(buf);
+        let n = buf.len();
+        len += n as u64;
+        reader.consume(n);
+    }
+
+    Some((len, hasher.finish()))
+}
+
+/// Patterns for the files left out with --exclude-channel and
+/// --exclude-network.
+fn excluded_files(settings: &Settings) -> Result<Vec<Regex>, regex::Error> {
+    let channels = settings
+        .exclude_channels
+        .iter()
+        .map(|c| log_file_pattern(".*", c));
+    let networks = settings
+        .exclude_networks
+        .iter()
+        .map(|n| log_file_pattern(n, ".*"));
+
+    channels.chain(networks).collect()
+}
+
+/// Matches the names of log files for the network and channel. The channel's
+/// leading `#`s are optional, so `rust` and `#rust` find the same files.
+fn log_file_pattern(network: &str, channel: &str) -> Result<Regex, regex::Error> {
+    let file_pattern = format!(
+        "^irc\\.{}\\.#*{}\\.weechatlog$",
+        network,
+        channel.trim_start_matches('#')
+    );
+    Regex::new(&file_pattern)
+}
+
+/// Splits a buffer name such as `irc.libera.#rust`, or the name of its log
+/// file, into the network and the channel.
+fn split_target(target: &str) -> Option<(&str, &str)> {
+    let target = target.strip_suffix(".weechatlog").unwrap_or(target);
+    let rest = target.strip_prefix("irc.")?;
+    let (network, channel) = rest.split_once('.')?;
+
+    if network.is_empty() || channel.trim_start_matches('#').is_empty() {
+        return None;
+    }
+
+    Some((network, channel))
+}
+
+/// The network and channel a log file is for, going by its name.
+fn file_target(filename: &path::Path) -> Option<(String, String)> {
+    let name = filename.file_name()?.to_str()?;
+    let (network, channel) = split_target(name)?;
+    Some((network.to_string(), channel.to_string()))
+}
+
+/// Turns a buffer name into patterns matching just that network and channel.
+fn parse_target(target: &str) -> Option<(String, String)> {
+    let (network, channel) = split_target(target)?;
+    Some((
+        regex::escape(network),
+        regex::escape(channel.trim_start_matches('#')),
+    ))
+}
+
+/// Finds the date in the names of log files, for logger.file.mask settings
+/// like `$plugin.$name.%Y-%m-%d.weechatlog`.
+fn log_file_date() -> Regex {
+    Regex::new(r"\.(\d{4}-\d{2}-\d{2})\.weechatlog$").unwrap()
+}
+
+/// Whether a log with `date` in its name can have lines within --since and
+/// --until, so that logs from other days needn't be read at all.
+fn date_in_range(settings: &Settings, date: &str) -> bool {
+    let since = settings.since.as_deref().map(|s| s.get(..10).unwrap_or(s));
+    since.is_none_or(|s| date >= s) && settings.until.as_deref().is_none_or(|u| date < u)
+}
+
+/// Sorts log files by the date in their names, and then by path. Files
+/// without a date come first.
+fn sort_log_files(files: &mut [path::PathBuf]) {
+    let date = log_file_date();
+
+    files.sort_by_cached_key(|p| {
+        let name = p.file_name().and_then(OsStr::to_str).unwrap_or("");
+        let d = date.captures(name).map(|c| c[1].to_string());
+        (d, p.clone())
+    });
+}
+
+fn parse_delimiter(d: &str) -> Option<char> {
+    if d == "\\t" {
+        return Some('\t');
+    }
+
+    let mut chars = d.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Decides whether to color the output. --color=always wins over NO_COLOR,
+/// which wins over checking whether we're writing to a terminal.
+fn use_color(when: &str, no_color: bool, is_terminal: impl FnOnce() -> bool) -> bool {
+    match when {
+        "always" => true,
+        "never" => false,
+        _ => !no_color && is_terminal(),
+    }
+}
+
+/// An option that the config file can turn on, and `off`, its `--no-` form,
+/// turns off again. Whichever of the two comes last on the command line wins
+/// over the config file.
+fn switch(matches: &clap::ArgMatches, on: &str, off: &str, default: Option<bool>) -> bool {
+    if matches.is_present(on) {
+        true
+    } else if matches.is_present(off) {
+        false
+    } else {
+        default.unwrap_or(false)
+    }
+}
+
+fn parse_number(option: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .map_err(|_| format!("{} expects a number, got '{}'", option, value))
+}
+
+/// Like `parse_number` but for options where 0 makes no sense.
+fn parse_positive(option: &str, value: &str) -> Result<usize, String> {
+    match parse_number(option, value)? {
+        0 => Err(format!("{} must be at least 1", option)),
+        n => Ok(n),
+    }
+}
+
+/// Parses a duration such as `90m`, `24h`, `7d` or `2w`.
+fn parse_duration(option: &str, value: &str) -> Result<std::time::Duration, String> {
+    let err = || {
+        format!(
+            "{} expects a duration like 24h or 7d, got '{}'",
+            option, value
+        )
+    };
+
+    let split = value.len() - value.chars().last().map_or(0, char::len_utf8);
+    let (n, unit) = value.split_at(split);
+    let n = n.parse::<u64>().map_err(|_| err())?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(err()),
+    };
+
+    let secs = n.checked_mul(secs).ok_or_else(err)?;
+    Ok(std::time::Duration::from_secs(secs))
+}
+
+/// Checks that the options make sense together and compiles the patterns.
+fn validate_settings(settings: &mut Settings) -> Result<(), IrcgrepError> {
+    check_settings(settings).map_err(IrcgrepError::InvalidSettings)?;
+
+    if let Some(f) = &settings.fields_string {
+        settings.fields = Some(
+            FieldRegex::parse(f)
+                .map_err(|e| IrcgrepError::InvalidSettings(format!("--field-regex: {}", e)))?,
+        );
+    }
+
+    if let Some(h) = &settings.host_string {
+        settings.host = Some(Regex::new(h)?);
+    }
+
+    if let Some(b) = &settings.bot_string {
+        settings.bots = Some(Regex::new(b)?);
+    }
+
+    settings.query = compile_query(settings)?;
+
+    Ok(())
+}
+
+/// The rules for which options go together.
+fn check_settings(settings: &Settings) -> Result<(), String> {
+    // --strip-joins still decides which lines get counted, and
+    // --strip-timestamps has nothing to strip, but context lines would just
+    // be thrown away.
+    if settings.count && settings.context > 0 {
+        return Err(String::from("Can't combine --count with --context"));
+    }
+
+    if settings.percent && !settings.count {
+        return Err(String::from("--percent needs --count"));
+    }
+
+    if settings.sort.is_some() && !settings.count {
+        return Err(String::from("--sort needs --count"));
+    }
+
+    if settings.min_count > 0 && !settings.count {
+        return Err(String::from("--min-count needs --count"));
+    }
+
+    if settings.count_to_stderr && !settings.count {
+        return Err(String::from("--count-to-stderr needs --count"));
+    }
+
+    if settings.churn && settings.count {
+        return Err(String::from("Can't combine --churn with --count"));
+    }
+
+    if settings.interactive && (settings.count || settings.churn) {
+        return Err(String::from(
+            "Can't combine --interactive with --count or --churn",
+        ));
+    }
+
+    if settings.unique_nicks && (settings.count || settings.churn) {
+        return Err(String::from(
+            "Can't combine --unique-nicks with --count or --churn",
+        ));
+    }
+
+    if settings.only_nicks
+        && (settings.count || settings.churn || settings.unique_nicks || settings.interactive)
+    {
+        return Err(String::from(
+            "Can't combine --only-nicks with --count, --churn, --unique-nicks or --interactive",
+        ));
+    }
+
+    if settings.list_files && (settings.count || settings.churn || settings.interactive) {
+        return Err(String::from(
+            "Can't combine --files-with-matches with --count, --churn or --interactive",
+        ));
+    }
+
+    if settings.host_string.is_some() && (settings.strip_joins || settings.content_only) {
+        return Err(String::from(
+            "Can't combine --host with --strip-joins or --content-only",
+        ));
+    }
+
+    if settings.json
+        && (settings.count
+            || settings.churn
+            || settings.list_files
+            || settings.interactive
+            || settings.unique_nicks)
+    {
+        return Err(String::from(
+            "--json can't be combined with --count, --churn, --files-with-matches, --interactive or --unique-nicks",
+        ));
+    }
+
+    if settings.matrix
+        && (settings.count
+            || settings.churn
+            || settings.list_files
+            || settings.interactive
+            || settings.unique_nicks
+            || settings.json)
+    {
+        return Err(String::from(
+            "Can't combine --matrix with --count, --churn, --files-with-matches, --interactive, --unique-nicks or --json",
+        ));
+    }
+
+    if settings.heatmap.is_some()
+        && (settings.count
+            || settings.churn
+            || settings.list_files
+            || settings.interactive
+            || settings.unique_nicks
+            || settings.json
+            || settings.matrix)
+    {
+        return Err(String::from(
+            "Can't combine --heatmap with --count, --churn, --files-with-matches, --interactive, --unique-nicks, --json or --matrix",
+        ));
+    }
+
+    if settings.exec.is_some()
+        && (settings.count
+            || settings.churn
+            || settings.list_files
+            || settings.interactive
+            || settings.unique_nicks
+            || settings.json
+            || settings.matrix
+            || settings.heatmap.is_some())
+    {
+        return Err(String::from(
+            "Can't combine --exec with options that choose what to print",
+        ));
+    }
+
+    if settings.multiline > 0
+        && (settings.count
+            || settings.churn
+            || settings.list_files
+            || settings.interactive
+            || settings.unique_nicks
+            || settings.only_nicks
+            || settings.json
+            || settings.matrix
+            || settings.heatmap.is_some()
+            || settings.exec.is_some())
+    {
+        return Err(String::from(
+            "--multiline only works when printing the matching lines",
+        ));
+    }
+
+    if settings.replace.is_some() && settings.nick_message {
+        return Err(String::from("Can't combine --replace with --nick-message"));
+    }
+
+    if settings.or && !filters_nick(settings) {
+        return Err(String::from("--or needs --nickname"));
+    }
+
+    if settings.invert_nick && !filters_nick(settings) {
+        return Err(String::from("--invert-nick needs --nickname"));
+    }
+
+    // --to on its own finds everyone's mentions of the nick.
+    if !filters_nick(settings) && settings.pattern_string.is_empty() && settings.mention.is_none() {
+        return Err(String::from("Must give either --pattern or --nickname"));
+    }
+
+    if settings.context > settings.max_context {
+        return Err(format!(
+            "--context {} is larger than --max-context {}",
+            settings.context, settings.max_context
+        ));
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let mut settings = Settings {
+        channel: String::from(".*"),
+        network: String::from(".*"),
+        ..Default::default()
+    };
+
+    if let Err(e) = search(&mut settings) {
+        let quiet = match e {
+            IrcgrepError::BrokenPipe | IrcgrepError::CommandFailed => true,
+            IrcgrepError::NoFiles(_) => settings.no_messages,
+            _ => false,
+        };
+        if !quiet {
+            eprintln!("ircgrep: {}", e);
+        }
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// What --fail-fast stops with once a file couldn't be read.
+fn fail_fast_error() -> IrcgrepError {
+    IrcgrepError::IoError(std::io::Error::other(
+        "stopped at the first file that couldn't be read",
+    ))
+}
+
+/// The command line options.
+fn app() -> clap::App<'static, 'static> {
+    clap_app!(ircgrep =>
+        (version: "0.1.0")
+        (author: "Øystein Walle <oystwa@gmail.com>")
+        (after_help: "The log directory is --log-dir if given, otherwise the first of these that exists:\n    \
+                      $WEECHAT_HOME/logs\n    \
+                      $XDG_DATA_HOME/weechat/logs (default ~/.local/share/weechat/logs)\n    \
+                      ~/.weechat/logs\n\n\
+                      The exit status is 1 if the options are wrong or there are no logs to search, and 2 if\n\
+                      output can't be written, an --exec command fails or --fail-fast stops at a log that\n\
+                      can't be read.")
+        (@arg NICKNAME: -n --nickname +takes_value "nickname")
+        (@arg NICK_FILE: --("nick-file") +takes_value "match any of the nicks in this file, one per line, like --nickname; blank lines and lines starting with # are ignored")
+        (@arg FROM:     --from +takes_value conflicts_with[NICKNAME] "only messages written by this nick, same as --nickname")
+        (@arg TO:       --to   +takes_value "only messages that mention this nick, which is highlighted")
+        (@arg KEEP_PREFIX: --("keep-nick-prefix") "keep the @ of ops and + of voiced nicks, so --nickname and the output include it")
+        (@arg OPS:      --("ops-only") conflicts_with[VOICED] "only messages from ops, whose nicks start with @")
+        (@arg VOICED:   --("voiced-only")          "only messages from voiced nicks, which start with +")
+        (@arg CHANNEL:  -c --channel  +takes_value "channel, with or without the leading #")
+        (@arg PATTERN:  -e --pattern  +takes_value ... number_of_values(1) "pattern, can be given more than once to match any of them")
+        (@arg POS_PATTERN: value_name("PATTERN") conflicts_with[PATTERN] "pattern, same as --pattern")
+        (@arg NETWORK:  -N --network  +takes_value default_value(".*") "network")
+        (@arg EXCL_CHAN: --("exclude-channel") +takes_value ... number_of_values(1) "don't search this channel, can be given more than once")
+        (@arg EXCL_NET: --("exclude-network") +takes_value ... number_of_values(1) "don't search this network, can be given more than once")
+        (@arg TARGET:   --target +takes_value conflicts_with[CHANNEL NETWORK] "network and channel in one, like irc.libera.#rust")
+        (@arg OR:       --or                       "match lines from --nickname OR matching --pattern (by default both must match)")
+        (@arg INVERT:   -v --invert                "select lines that don't match --pattern; with --nickname and no pattern, select lines from everyone except --nickname")
+        (@arg INV_NICK: --("invert-nick")          "select lines from everyone except --nickname; combines with -v and --or as if the nick and pattern had been inverted up front, and -v with no pattern inverts it back")
+        (@arg FIELDS:   --("field-regex") +takes_value "only match lines whose fields match these regexes as well, like 'nick:/^alice$/ msg:/hi/'")
+        (@arg IGNORE_BOTS: --("ignore-bots")      "leave out messages from bots, taken to be nicks ending in bot or serv")
+        (@arg BOT_PATTERN: --("bot-pattern") +takes_value requires[IGNORE_BOTS] "regex for the nicks --ignore-bots leaves out instead of the default (?i)(bot|serv)$")
+        (@arg HOST:     --host +takes_value         "only match joins, parts and quits whose user@host matches this regex")
+        (@arg TEXT:     -a --text                  "search lines that aren't valid UTF-8, replacing the invalid parts")
+        (@arg ENCODING: --encoding +takes_value possible_values(&["utf8", "latin1", "cp1252"]) "what the logs are written in, utf8 by default")
+        (@arg NICK_MSG: --("nick-message")         "match the pattern against \"nick: message\" instead of just the message")
+        (@arg MATCH_TS: --("match-timestamp") conflicts_with[NICK_MSG STRIP_TS TO REPLACE] "match the pattern against the time stamp instead of the message, e.g. ':00:00$'")
+        (@arg FIXED:    -f --fixed                 "fixed string search")
+        (@arg WORD:     -w --("word-regexp")       "only match whole words")
+        (@arg WORD_CHARS: --("word-chars") +takes_value "characters that count as part of a word with -w besides letters, digits and _, e.g. '-/' for paths or '-[]\\`^{}|' for nicks")
+        (@arg STRIP_TS: -d --("strip-timestamps")  "strip time stamps")
+        (@arg STRIP_J:  -j --("strip-joins")       "strip joins/leaves and whatnot")
+        (@arg NO_STRIP_TS: --("no-strip-timestamps") overrides_with[STRIP_TS] "keep time stamps even if the config file says to strip them")
+        (@arg NO_STRIP_J: --("no-strip-joins") overrides_with[STRIP_J] "keep joins/leaves even if the config file says to strip them")
+        (@arg DATE:     --date +takes_value conflicts_with[TODAY YESTERDAY LAST_WEEK] "only search messages from this day, given as YYYY-MM-DD")
+        (@arg TODAY:    --today     conflicts_with[YESTERDAY LAST_WEEK] "only search today's messages")
+        (@arg YESTERDAY: --yesterday conflicts_with[LAST_WEEK]          "only search yesterday's messages")
+        (@arg LAST_WEEK: --("last-week")                               "only search the last seven days")
+        (@arg SINCE_LAST: --("since-last") "only report lines newer than the last ones seen in each log the last time --since-last was used")
+        (@arg NO_UPDATE: --("no-update") requires[SINCE_LAST] "with --since-last, don't record where this run left off")
+        (@arg MOD_SINCE: --("modified-since") +takes_value "only search files modified within this long, like 24h or 7d; combines with the date options, which then pick lines from those files")
+        (@arg CONTENT:  --("content-only")         "only show messages from people, no joins, server notices or errors")
+        (@arg BLANK:    --("skip-blank")           "skip empty and whitespace-only messages")
+        (@arg NO_CONTENT: --("no-content-only") overrides_with[CONTENT] "show every line even if the config file sets content_only")
+        (@arg NO_BLANK: --("no-skip-blank") overrides_with[BLANK] "keep empty messages even if the config file sets skip_blank")
+        (@arg FIRST_ONLY: --("first-only") conflicts_with[PERCENT] "only print the first match in each file, with its context")
+        (@arg SQUEEZE:  --("squeeze-blank") conflicts_with[BLANK] "print only the first of several empty or whitespace-only messages in a row")
+        (@arg JOIN_PASTES: --("join-pastes") conflicts_with[INTERACTIVE] "treat lines from the same nick at most a second apart as one message, joined with spaces, so patterns can match across a paste")
+        (@arg MULTILINE: --multiline +takes_value conflicts_with[CONTEXT CTX_MATCHES INVERT OR INV_NICK NICK_MSG MATCH_TS TO REPLACE BYTE_OFFSET] "match the pattern against this many messages at a time, joined by newlines, so it can span lines; slower the more messages")
+        (@arg DELIM:    --delimiter +takes_value   "character separating the time stamp, nick and message [default: tab]")
+        (@arg OUT_DELIM: --("output-delimiter") +takes_value "print this between the time stamp, nick and message, and after the file name with --count, instead of what the log uses")
+        (@arg CONTEXT:  -C --context  +takes_value "context lines")
+        (@arg CTX_MATCHES: --("context-matches") +takes_value conflicts_with[CONTEXT] "like --context but counts and shows only messages from people, leaving out joins, parts and other events")
+        (@arg MAX_CTX:  --("max-context") +takes_value default_value("10000") "largest number of context lines allowed")
+        (@arg COUNT:    -t --count                 "print the number of matching lines in each file")
+        (@arg COUNT_LINES: --("count-lines")     "count matching lines, same as --count")
+        (@arg COUNT_MATCHES: --("count-matches") conflicts_with[COUNT_LINES] "count every match instead of matching lines; a line selected on the nick alone or with -v counts once")
+        (@arg COUNT_ONCE: --("count-once")       "count a line with several matches once, even with --count-matches; implies --count")
+        (@arg INV_COUNT: --("invert-count") conflicts_with[COUNT_MATCHES] "count the lines that don't match, same as --count --invert")
+        (@arg SORT:     --sort +takes_value possible_values(&["count", "name", "date"]) "order the output of --count by number of matches, file name or date")
+        (@arg PERCENT:  --percent                  "also show the matching lines as a percentage of all lines with --count")
+        (@arg MIN_COUNT: --("min-count") +takes_value "leave out files with fewer matches than this with --count")
+        (@arg COUNT_STDERR: --("count-to-stderr")  "print the counts from --count on stderr instead of stdout")
+        (@arg CHURN:    --churn                    "count joins, parts and quits in each file")
+        (@arg INTERACTIVE: --interactive           "browse the matches in a full screen list")
+        (@arg DEDUP:    --dedup                    "drop a match if the same nick said the same thing right before")
+        (@arg DEDUP_WIN: --("dedup-window") +takes_value "drop a match if the same nick said the same thing in the last N matches")
+        (@arg LIST:     -l --("files-with-matches") "only print the paths of files with matches")
+        (@arg NULL:     -Z --null                  "end paths printed by -l with a NUL instead of a newline")
+        (@arg QUOTE:    --quote requires[LIST] conflicts_with[NULL] "quote paths printed by -l so a shell can use them as they are")
+        (@arg UNIQUE:   --("unique-nicks")         "print how many different nicks said something matching")
+        (@arg NICKLIST: --list requires[UNIQUE]    "also list the nicks with --unique-nicks")
+        (@arg ONLY_NICKS: --("only-nicks")         "only print who said something matching, one nick per line")
+        (@arg JSON:     --json                     "print each match as a JSON object on its own line; match_count is the number of ranges, or 1 for a line selected on the nick alone or with -v")
+        (@arg JSON_ARRAY: --("json-array") conflicts_with[JSON] "print the matches as a single JSON array, with a summary object at the end")
+        (@arg SUMMARY_ONLY: --("summary-only") conflicts_with[INTERACTIVE JSON] "instead of the matches, counts or tables print one line with the number of matches, the number of nicks and the days they're from")
+        (@arg MATRIX:   --matrix                   "print CSV with the number of matching lines per nick (columns) and day (rows)")
+        (@arg HEATMAP:  --heatmap +takes_value min_values(0) require_equals(true) possible_values(&["csv", "ascii"]) "print the number of matching lines per weekday and hour, as csv (the default) or ascii")
+        (@arg EXEC:     --exec +takes_value        "run a command for each matching line, given in place of {} or on stdin")
+        (@arg EXEC_BATCH: --("exec-batch") +takes_value conflicts_with[EXEC] "like --exec but run the command once per file with all the matching lines")
+        (@arg WITH_NAME: -H --("with-filename")    "print the file name before each line")
+        (@arg BYTE_OFFSET: -b --("byte-offset")    "print the byte offset in the file where each line starts before it")
+        (@arg SHOW_TARGET: --("show-target")       "like -H but print the network and channel instead of the file name")
+        (@arg MARKERS:  --("file-markers")         "print a line like --- irc.libera.#rust [2020-06-23] --- where the output from each file begins")
+        (@arg GROUP:    --group                    "print a header before the matches from each file")
+        (@arg GROUP_SEP: --("group-separator") +takes_value requires[GROUP] "print this line between the files with --group, a blank line by default")
+        (@arg NO_GROUP_SEP: --("no-group-separator") requires[GROUP] conflicts_with[GROUP_SEP] "don't print anything between the files with --group")
+        (@arg FULLPATH: --("full-path")            "show the full path of each file, same as --path-style absolute")
+        (@arg PATH_STYLE: --("path-style") +takes_value possible_values(&["name", "absolute", "relative"]) conflicts_with[FULLPATH] "show files by name (the default), absolute path or path relative to the log directory")
+        (@arg CONFIG:   --config +takes_value      "read defaults from this file instead of ~/.config/ircgrep/config.toml; sections like [channel.\"#rust\"] apply when searching that channel")
+        (@arg LOG_DIR:  -L --("log-dir") +takes_value "directory containing the logs")
+        (@arg RECURSIVE: -r --recursive           "also search logs in subdirectories of the log directory")
+        (@arg MAX_DEPTH: --("max-depth") +takes_value requires[RECURSIVE] "only go this many levels of directories deep with -r, where 1 is just the log directory")
+        (@arg DEDUP_FILES: --("dedup-files")      "only search one of several logs with the same contents, such as copies in different directories")
+        (@arg FILES_FROM: --("files-from") +takes_value conflicts_with[LOG_DIR] "search the files listed in this file, one per line, instead of the log directory; - reads the list from stdin")
+        (@arg NO_MSGS:  -s --("no-messages")       "don't warn about files and directories that can't be read")
+        (@arg FAIL_FAST: --("fail-fast")          "stop with exit status 2 at the first file that can't be read instead of carrying on")
+        (@arg VERBOSE:  --verbose                  "report how many files were searched and matches found on stderr")
+        (@arg BUF_SIZE: --("buffer-size") +takes_value "number of bytes to read from the logs at a time")
+        (@arg PROGRESS: --progress                 "show which file is being searched on stderr, and with --count how many matches there have been so far")
+        (@arg COLOR:    --color +takes_value possible_values(&["auto", "always", "never"]) default_value("auto") "when to color the output; auto means when writing to a terminal and NO_COLOR isn't set")
+        (@arg THEME:    --theme +takes_value possible_values(&["default", "solarized", "mono"]) "the colors to use, mono being for terminals with few colors")
+        (@arg ACTIONS:  --("render-actions")       "print /me actions as \"* nick does something\"")
+        (@arg NO_ACTIONS: --("no-render-actions") overrides_with[ACTIONS] "print /me actions as they're logged even if the config file sets render_actions")
+        (@arg REPLACE:  --replace +takes_value     "print matches replaced by this, where $1 and so on are the groups captured by the pattern (the logs aren't changed)")
+        (@arg SNIPPET:  --snippet +takes_value     "only show this many characters on either side of each match")
+        (@arg WRAP:     --wrap conflicts_with[TRUNCATE] "wrap long messages at the width of the terminal")
+        (@arg TRUNCATE: --truncate                 "cut long messages off at the width of the terminal")
+        (@arg HL_NICK:  --("highlight-nick")       "highlight the nick in lines that only matched --nickname")
+        (@arg DIM:      --("dim-context")          "dim everything but the matches, context lines included, instead of highlighting the matches")
+        (@arg MARK:     --("mark-matches")         "wrap matches in [[ and ]], for output that won't be colored")
+        (@arg PREFIX:   --("match-prefix") +takes_value "print this before each match")
+        (@arg SUFFIX:   --("match-suffix") +takes_value "print this after each match")
+        (@arg LINE_BUF: --("line-buffered")        "flush output after every line (default when writing to a terminal)")
+    )
+}
+
+/// Fills in `settings` from the parsed command line and checks them.
+fn parse_args(
+    settings: &mut Settings,
+    matches: &clap::ArgMatches,
+    now: DateTime<Local>,
+) -> Result<(), IrcgrepError> {
+    if let Some(n) = matches.value_of("NICKNAME").or(matches.value_of("FROM")) {
+        settings.nickname = n.to_string();
+    }
+    if let Some(f) = matches.value_of("NICK_FILE") {
+        let list = fs::read_to_string(f)
+            .map_err(|e| IrcgrepError::InvalidSettings(format!("--nick-file {}: {}", f, e)))?;
+        settings.nick_list = Some(parse_nick_list(&list));
+    }
+    settings.mention = matches.value_of("TO").map(String::from);
+    settings.keep_nick_prefix = matches.is_present("KEEP_PREFIX");
+    if matches.is_present("OPS") {
+        settings.role = Some('@');
+    } else if matches.is_present("VOICED") {
+        settings.role = Some('+');
+    }
+    if let Some(c) = matches.value_of("CHANNEL") {
+        settings.channel = c.to_string();
+    }
+    if let Some(p) = matches.value_of("POS_PATTERN") {
+        settings.pattern_string = p.to_string();
+    } else if let Some(mut patterns) = matches.values_of("PATTERN") {
+        settings.pattern_string = patterns.next().unwrap_or_default().to_string();
+        settings.extra_patterns = patterns.map(String::from).collect();
+    } else if !filters_nick(settings) && settings.mention.is_none() {
+        // With nothing at all to go by every message matches. When there's
+        // a nick the lines match on it alone, so that --highlight-nick has
+        // something to highlight and --to highlights just the mention.
+        settings.pattern_string = String::from(".*");
+    }
+    if let Some(n) = matches.value_of("NETWORK") {
+        settings.network = n.to_string();
+    }
+    let values = |name| {
+        matches
+            .values_of(name)
+            .map_or(Vec::new(), |v| v.map(String::from).collect())
+    };
+    settings.exclude_channels = values("EXCL_CHAN");
+    settings.exclude_networks = values("EXCL_NET");
+    if let Some(t) = matches.value_of("TARGET") {
+        let (network, channel) = parse_target(t).ok_or_else(|| {
+            IrcgrepError::InvalidSettings(format!(
+                "--target expects a buffer name like irc.libera.#rust, got '{}'",
+                t
+            ))
+        })?;
+        settings.network = network;
+        settings.channel = channel;
+    }
+    // The config file fills in what wasn't given, with the searched channel's
+    // section taking precedence over the rest of the file.
+    let defaults = config::load(matches.value_of("CONFIG"), |v| env::var(v).ok())
+        .map_err(IrcgrepError::InvalidSettings)?
+        .defaults_for(&settings.channel);
+    if matches.occurrences_of("NETWORK") == 0 && !matches.is_present("TARGET") {
+        if let Some(n) = defaults.network {
+            settings.network = n;
+        }
+    }
+    settings.host_string = matches.value_of("HOST").map(String::from);
+    if matches.is_present("IGNORE_BOTS") {
+        let pattern = matches.value_of("BOT_PATTERN").unwrap_or(BOT_PATTERN);
+        settings.bot_string = Some(pattern.to_string());
+    }
+    settings.fields_string = matches.value_of("FIELDS").map(String::from);
+    settings.fixed = matches.is_present("FIXED");
+    settings.word = matches.is_present("WORD");
+    settings.word_chars = matches.value_of("WORD_CHARS").unwrap_or("").to_string();
+    settings.text = matches.is_present("TEXT");
+    settings.encoding = match matches.value_of("ENCODING") {
+        Some("latin1") => Encoding::Latin1,
+        Some("cp1252") => Encoding::Cp1252,
+        _ => Encoding::Utf8,
+    };
+    settings.nick_message = matches.is_present("NICK_MSG");
+    settings.match_timestamp = matches.is_present("MATCH_TS");
+    settings.or = matches.is_present("OR");
+    settings.invert = matches.is_present("INVERT") || matches.is_present("INV_COUNT");
+    settings.invert_nick = matches.is_present("INV_NICK");
+    settings.strip_time_stamps = switch(
+        matches,
+        "STRIP_TS",
+        "NO_STRIP_TS",
+        defaults.strip_timestamps,
+    );
+    settings.strip_joins = switch(matches, "STRIP_J", "NO_STRIP_J", defaults.strip_joins);
+    settings.content_only = switch(matches, "CONTENT", "NO_CONTENT", defaults.content_only);
+    let relative = if matches.is_present("TODAY") {
+        Some("today")
+    } else if matches.is_present("YESTERDAY") {
+        Some("yesterday")
+    } else if matches.is_present("LAST_WEEK") {
+        Some("last-week")
+    } else {
+        None
+    };
+    let range = match matches.value_of("DATE") {
+        Some(d) => Some(date_range(d).ok_or_else(|| {
+            IrcgrepError::InvalidSettings(format!("--date expects YYYY-MM-DD, got '{}'", d))
+        })?),
+        None => relative.and_then(|r| relative_range(r, now.date_naive())),
+    };
+    if let Some((since, until)) = range {
+        settings.since = Some(since.format("%Y-%m-%d").to_string());
+        settings.until = until.map(|u| u.format("%Y-%m-%d").to_string());
+    }
+    if matches.is_present("SINCE_LAST") {
+        let file = config::dir(|v| env::var(v).ok())
+            .ok_or_else(|| {
+                IrcgrepError::InvalidSettings(String::from(
+                    "--since-last needs $XDG_CONFIG_HOME or $HOME to keep its state in",
+                ))
+            })?
+            .join("since-last.json");
+        settings.since_last = Some(Bookmarks::load(file).map_err(IrcgrepError::InvalidSettings)?);
+    }
+    settings.no_update = matches.is_present("NO_UPDATE");
+    if let Some(d) = matches.value_of("MOD_SINCE") {
+        settings.modified_since =
+            Some(parse_duration("--modified-since", d).map_err(IrcgrepError::InvalidSettings)?);
+    }
+    settings.recursive = matches.is_present("RECURSIVE");
+    settings.dedup_files = matches.is_present("DEDUP_FILES");
+    if let Some(n) = matches.value_of("MAX_DEPTH") {
+        settings.max_depth =
+            Some(parse_positive("--max-depth", n).map_err(IrcgrepError::InvalidSettings)?);
+    }
+    settings.skip_blank = switch(matches, "BLANK", "NO_BLANK", defaults.skip_blank);
+    settings.squeeze_blank = matches.is_present("SQUEEZE");
+    settings.first_only = matches.is_present("FIRST_ONLY");
+    settings.join_pastes = matches.is_present("JOIN_PASTES");
+    if let Some(n) = matches.value_of("MULTILINE") {
+        settings.multiline =
+            parse_positive("--multiline", n).map_err(IrcgrepError::InvalidSettings)?;
+    }
+    settings.context_matches = matches.is_present("CTX_MATCHES");
+    let context = match matches.value_of("CTX_MATCHES") {
+        Some(c) => parse_number("--context-matches", c),
+        None => matches
+            .value_of("CONTEXT")
+            .map_or(Ok(0), |c| parse_number("--context", c)),
+    };
+    let max_context = parse_number("--max-context", matches.value_of("MAX_CTX").unwrap());
+    settings.dedup_window = match matches.value_of("DEDUP_WIN") {
+        Some(n) => parse_number("--dedup-window", n).map_err(IrcgrepError::InvalidSettings)?,
+        None if matches.is_present("DEDUP") => 1,
+        None => 0,
+    };
+    if let Some(n) = matches.value_of("BUF_SIZE") {
+        settings.buffer_size =
+            Some(parse_positive("--buffer-size", n).map_err(IrcgrepError::InvalidSettings)?);
+    }
+    settings.context = context.map_err(IrcgrepError::InvalidSettings)?;
+    settings.max_context = max_context.map_err(IrcgrepError::InvalidSettings)?;
+    settings.count_matches = matches.is_present("COUNT_MATCHES");
+    settings.count_once = matches.is_present("COUNT_ONCE");
+    settings.count = matches.is_present("COUNT")
+        || matches.is_present("COUNT_LINES")
+        || matches.is_present("INV_COUNT")
+        || settings.count_matches
+        || settings.count_once;
+    settings.percent = matches.is_present("PERCENT");
+    settings.count_to_stderr = matches.is_present("COUNT_STDERR");
+    if let Some(n) = matches.value_of("MIN_COUNT") {
+        settings.min_count =
+            parse_number("--min-count", n).map_err(IrcgrepError::InvalidSettings)?;
+    }
+    settings.sort = match matches.value_of("SORT") {
+        Some("count") => Some(SortBy::Count),
+        Some("name") => Some(SortBy::Name),
+        Some("date") => Some(SortBy::Date),
+        _ => None,
+    };
+    settings.path_style = match matches.value_of("PATH_STYLE") {
+        Some("absolute") => PathStyle::Absolute,
+        // The log directory isn't known yet.
+        Some("relative") => PathStyle::Relative(path::PathBuf::new()),
+        _ if matches.is_present("FULLPATH") => PathStyle::Absolute,
+        _ => PathStyle::Name,
+    };
+    settings.churn = matches.is_present("CHURN");
+    settings.group = matches.is_present("GROUP");
+    if !matches.is_present("NO_GROUP_SEP") {
+        settings.group_separator = Some(matches.value_of("GROUP_SEP").unwrap_or("").to_string());
+    }
+    settings.file_markers = matches.is_present("MARKERS");
+    settings.with_filename = matches.is_present("WITH_NAME");
+    settings.show_target = matches.is_present("SHOW_TARGET");
+    settings.json_array = matches.is_present("JSON_ARRAY");
+    settings.json = matches.is_present("JSON") || settings.json_array;
+    settings.list_files = matches.is_present("LIST");
+    settings.null = matches.is_present("NULL");
+    settings.quote = matches.is_present("QUOTE");
+    settings.unique_nicks = matches.is_present("UNIQUE");
+    settings.matrix = matches.is_present("MATRIX");
+    settings.summary_only = matches.is_present("SUMMARY_ONLY");
+    if matches.is_present("HEATMAP") {
+        settings.heatmap = match matches.value_of("HEATMAP") {
+            Some("ascii") => Some(Heatmap::Ascii),
+            _ => Some(Heatmap::Csv),
+        };
+    }
+    settings.exec_batch = matches.is_present("EXEC_BATCH");
+    settings.exec = matches
+        .value_of("EXEC")
+        .or(matches.value_of("EXEC_BATCH"))
+        .map(String::from);
+    if let Some(command) = &settings.exec {
+        split_command(command).map_err(IrcgrepError::InvalidSettings)?;
+    }
+    settings.list = matches.is_present("NICKLIST");
+    settings.only_nicks = matches.is_present("ONLY_NICKS");
+    settings.interactive = matches.is_present("INTERACTIVE");
+    settings.verbose = matches.is_present("VERBOSE");
+    settings.no_messages = matches.is_present("NO_MSGS");
+    settings.fail_fast = matches.is_present("FAIL_FAST");
+    settings.progress = matches.is_present("PROGRESS") && std::io::stderr().is_terminal();
+    if let Some(d) = matches.value_of("DELIM") {
+        settings.delimiter = Some(parse_delimiter(d).ok_or_else(|| {
+            IrcgrepError::InvalidSettings(String::from("--delimiter must be a single character"))
+        })?);
+    }
+    settings.output_delimiter = matches
+        .value_of("OUT_DELIM")
+        .map(|d| d.replace("\\t", "\t"));
+    settings.color = use_color(
+        matches.value_of("COLOR").unwrap(),
+        env::var_os("NO_COLOR").is_some(),
+        || colored::control::SHOULD_COLORIZE.should_colorize(),
+    );
+    colored::control::set_override(settings.color);
+    if let Some(t) = matches.value_of("THEME") {
+        settings.theme = Theme::named(t).unwrap();
+    }
+    settings.highlight_nick = matches.is_present("HL_NICK");
+    settings.dim_context = matches.is_present("DIM");
+    settings.byte_offset = matches.is_present("BYTE_OFFSET");
+    settings.replace = matches.value_of("REPLACE").map(String::from);
+    settings.render_actions = switch(matches, "ACTIONS", "NO_ACTIONS", defaults.render_actions);
+    if let Some(n) = matches.value_of("SNIPPET") {
+        settings.snippet =
+            Some(parse_number("--snippet", n).map_err(IrcgrepError::InvalidSettings)?);
+    }
+    settings.fit = if matches.is_present("WRAP") {
+        Some(Fit::Wrap)
+    } else if matches.is_present("TRUNCATE") {
+        Some(Fit::Truncate)
+    } else {
+        None
+    };
+    settings.width = crossterm::terminal::size().map_or(80, |(w, _)| w as usize);
+    let mark = matches.is_present("MARK");
+    settings.match_prefix = match matches.value_of("PREFIX") {
+        Some(p) => p.to_string(),
+        None if mark => String::from("[["),
+        None => String::new(),
+    };
+    settings.match_suffix = match matches.value_of("SUFFIX") {
+        Some(s) => s.to_string(),
+        None if mark => String::from("]]"),
+        None => String::new(),
+    };
+    settings.line_buffered = matches.is_present("LINE_BUF") || stdout().is_terminal();
+    validate_settings(settings)?;
+
+    Ok(())
+}
+
+/// Parses the command line into `settings` and searches the way it asks.
+fn search(settings: &mut Settings) -> Result<(), IrcgrepError> {
+    let now = Local::now();
+    let matches = app().get_matches();
+    parse_args(settings, &matches, now)?;
+
+    // Where the files came from, for messages about them.
+    let (files, source) = match matches.value_of("FILES_FROM") {
+        Some(list) => (files_from(settings, list), format!("from {}", list)),
+        None => {
+            let log_dir = resolve_log_dir(matches.value_of("LOG_DIR"), |v| env::var(v).ok())
+                .ok_or_else(|| {
+                    IrcgrepError::InvalidSettings(String::from(
+                        "Could not find a home directory, use --log-dir",
+                    ))
+                })?;
+            if let PathStyle::Relative(base) = &mut settings.path_style {
+                *base = log_dir.clone();
+            }
+            let files = get_log_files(settings, &log_dir, now.into())?;
+            (files, format!("in {}", log_dir.display()))
+        }
+    };
+
+    let files = unique_files(settings, files);
+
+    if settings.fail_fast && settings.errors.get() > 0 {
+        return Err(fail_fast_error());
+    }
+
+    // Like grep, finding nothing to search is the same as finding no matches.
+    if files.is_empty() {
+        return Err(IrcgrepError::NoFiles(if matches.is_present("FILES_FROM") {
+            format!("no log files to search {}", source)
+        } else {
+            format!(
+                "no log files matched network={} channel={} {}",
+                settings.network, settings.channel, source
+            )
+        }));
+    }
+
+    let stdout = stdout();
+    let mut out: BufWriter<Box<dyn Write>> = if settings.count_to_stderr {
+        BufWriter::new(Box::new(stderr().lock()))
+    } else {
+        BufWriter::new(Box::new(stdout.lock()))
+    };
+
+    let mut total = 0;
+
+    if settings.interactive {
+        if !stdout.is_terminal() {
+            return Err(IrcgrepError::InvalidSettings(String::from(
+                "--interactive needs a terminal",
+            )));
+        }
+        let hits = collect_hits(settings, &files);
+        total = hits.len();
+        tui::run(&hits, settings.text, settings.encoding)?;
+    } else if settings.summary_only {
+        total = summary(settings, &files, &mut out)?;
+    } else if settings.unique_nicks {
+        total = unique_nicks(settings, &files, &mut out)?;
+    } else if settings.only_nicks {
+        total = only_nicks(settings, &files, &mut out)?;
+    } else if settings.matrix {
+        total = matrix(settings, &files, &mut out)?;
+    } else if let Some(format) = settings.heatmap {
+        total = heatmap(settings, &files, format, &mut out)?;
+    } else if settings.json_array {
+        total = json_array(settings, &files, &mut out)?;
+    } else if let (true, Some(by)) = (settings.count, settings.sort) {
+        total = sorted_counts(settings, &files, by, &mut out)?;
+    } else {
+        for (i, f) in files.iter().enumerate() {
+            if settings.progress {
+                show_progress(settings, i, files.len(), f, total);
+            }
+            total += search_file(settings, f, total > 0, &mut out)?;
+
+            if settings.fail_fast && settings.errors.get() > 0 {
+                out.flush()?;
+                return Err(fail_fast_error());
+            }
+        }
+
+        if settings.progress {
+            eprint!("\r\x1b[K");
+        }
+    }
+
+    out.flush()?;
+
+    // The modes that go through all the files at once only find out at the
+    // end.
+    if settings.fail_fast && settings.errors.get() > 0 {
+        return Err(fail_fast_error());
+    }
+
+    if settings.failed_commands.get() > 0 {
+        return Err(IrcgrepError::CommandFailed);
+    }
+
+    if let (Some(b), false) = (&settings.since_last, settings.no_update) {
+        b.save()?;
+    }
+
+    if settings.verbose {
+        eprintln!("ircgrep: searched {} files {}", files.len(), source);
+        if let Some(n) = settings.buffer_size {
+            eprintln!("ircgrep: read {} bytes at a time", n);
+        }
+        if total == 0 {
+            eprintln!("ircgrep: no matches found");
+        } else {
+            eprintln!("ircgrep: {} matches", total);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default, clippy::needless_borrow)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_line() {
+        let mut settings = Settings::default();
+        settings.nickname = String::from("osse");
+        settings.fixed = true;
+        settings.pattern_string = String::from("diagnosing");
+        let settings = compiled(settings);
+
+        let line = "2020-06-22 11:18:46	osse	check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo";
+        let lv = LineView::new(&line).unwrap();
+
+        let m = match_line(&settings, &lv);
+
+        let v = vec![(20, 30)];
+        assert_eq!(m, MatchType::Match(v));
+    }
+
+    #[test]
+    fn test_no_match_line() {
+        let mut settings = Settings::default();
+        settings.nickname = String::from("foo");
+        settings.fixed = true;
+        settings.pattern_string = String::from("diagnosing");
+        let settings = compiled(settings);
+
+        let line = "2020-06-22 11:18:46	osse	check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo";
+        let lv = LineView::new(&line).unwrap();
+
+        let m = match_line(&settings, &lv);
+
+        assert_eq!(m, MatchType::NoMatch);
+    }
+
+    #[test]
+    fn test_content_only() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("has"),
+            fixed: true,
+            content_only: true,
+            ..Default::default()
+        });
+
+        let line = "2020-06-22 11:40:05	<--	roadie (~user@example.com) has quit (Remote host closed the connection)";
+        assert_eq!(
+            match_line(&settings, &LineView::new(line).unwrap()),
+            MatchType::Skip
+        );
+
+        let line = "2020-06-22 11:40:05		- This server has a MOTD -";
+        assert_eq!(
+            match_line(&settings, &LineView::new(line).unwrap()),
+            MatchType::Skip
+        );
+
+        let line = "2020-06-22 11:40:06	osse	it has come to my attention";
+        assert_eq!(
+            match_line(&settings, &LineView::new(line).unwrap()),
+            MatchType::Match(vec![(3, 6)])
+        );
+    }
+
+    #[test]
+    fn test_match_line_or() {
+        let mut settings = compiled(Settings {
+            nickname: String::from("alice"),
+            fixed: true,
+            pattern_string: String::from("alice"),
+            ..Default::default()
+        });
+
+        let from_alice = "2020-06-22 11:00:00	alice	hello";
+        let about_alice = "2020-06-22 11:00:01	bob	where is alice?";
+        let neither = "2020-06-22 11:00:02	bob	hello";
+
+        let m = |s: &Settings, l| match_line(s, &LineView::new(l).unwrap());
+
+        assert_eq!(m(&settings, from_alice), MatchType::NoMatch);
+        assert_eq!(m(&settings, about_alice), MatchType::NoMatch);
+
+        settings.or = true;
+        assert_eq!(m(&settings, from_alice), MatchType::MatchNick);
+        assert_eq!(m(&settings, about_alice), MatchType::Match(vec![(9, 14)]));
+        assert_eq!(m(&settings, neither), MatchType::NoMatch);
+
+        // --or with only a nick has nothing to add to it.
+        settings.pattern_string = String::new();
+        assert_eq!(m(&settings, from_alice), MatchType::MatchNick);
+        assert_eq!(m(&settings, about_alice), MatchType::NoMatch);
+        assert_eq!(m(&settings, neither), MatchType::NoMatch);
+    }
+
+    #[test]
+    fn test_relative_range() {
+        let today = NaiveDate::from_ymd_opt(2020, 6, 22).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2020, 6, d).unwrap();
+
+        assert_eq!(relative_range("today", today), Some((day(22), None)));
+        assert_eq!(
+            relative_range("yesterday", today),
+            Some((day(21), Some(day(22))))
+        );
+        assert_eq!(relative_range("last-week", today), Some((day(16), None)));
+        assert_eq!(relative_range("tomorrow", today), None);
+
+        assert_eq!(date_range("2020-06-22"), Some((day(22), Some(day(23)))));
+        assert_eq!(
+            date_range("2020-06-30"),
+            Some((day(30), NaiveDate::from_ymd_opt(2020, 7, 1)))
+        );
+        assert_eq!(date_range("2020-06-31"), None);
+        assert_eq!(date_range("june"), None);
+    }
+
+    #[test]
+    fn test_match_line_date_range() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            since: Some(String::from("2020-06-21")),
+            until: Some(String::from("2020-06-22")),
+            ..Default::default()
+        });
+
+        let m = |l| match_line(&settings, &LineView::new(l).unwrap());
+
+        assert_eq!(m("2020-06-20 23:59:59	osse	hi"), MatchType::Skip);
+        assert_eq!(
+            m("2020-06-21 00:00:00	osse	hi"),
+            MatchType::Match(vec![(0, 2)])
+        );
+        assert_eq!(
+            m("2020-06-21 23:59:59	osse	hi"),
+            MatchType::Match(vec![(0, 2)])
+        );
+        assert_eq!(m("2020-06-22 00:00:00	osse	hi"), MatchType::Skip);
+    }
+
+    #[test]
+    fn test_match_line_nick_message() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("alice: hi"),
+            fixed: true,
+            nick_message: true,
+            ..Default::default()
+        });
+
+        let lv = LineView::new("2020-06-22 11:00:00	@alice	hi there").unwrap();
+        let m = match_line(&settings, &lv);
+        assert_eq!(m, MatchType::Match(vec![(0, 9)]));
+
+        let mut out = Vec::new();
+        if let MatchType::Match(v) = m {
+            print_line(&mut out, &settings, &lv, &v).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:00:00	alice	hi there\n"
+        );
+
+        let lv = LineView::new("2020-06-22 11:00:00	bob	alice: hi").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::Match(vec![(5, 14)]));
+    }
+
+    #[test]
+    fn test_match_line_host() {
+        let settings = Settings {
+            host: Some(Regex::new(r"@192\.0\.2\.").unwrap()),
+            ..Default::default()
+        };
+
+        let join =
+            LineView::new("2020-06-22 11:40:05	-->	roadie (~user@192.0.2.17) has joined #git")
+                .unwrap();
+        let other =
+            LineView::new("2020-06-22 11:40:05	-->	osse (osse@user/osse) has joined #git").unwrap();
+        let msg = LineView::new("2020-06-22 11:40:06	roadie	@192.0.2.17").unwrap();
+
+        assert_eq!(match_line(&settings, &join), MatchType::MatchNick);
+        assert_eq!(match_line(&settings, &other), MatchType::NoMatch);
+        assert_eq!(match_line(&settings, &msg), MatchType::NoMatch);
+    }
+
+    #[test]
+    fn test_match_line_patterns() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("git"),
+            extra_patterns: vec![String::from("h.")],
+            ..Default::default()
+        });
+
+        let lv = LineView::new("2020-06-22 11:00:00	osse	hg or git? h.").unwrap();
+        assert_eq!(
+            match_line(&settings, &lv),
+            MatchType::Match(vec![(0, 2), (6, 9), (11, 13)])
+        );
+
+        settings.fixed = true;
+        settings = compiled(settings);
+        assert_eq!(
+            match_line(&settings, &lv),
+            MatchType::Match(vec![(6, 9), (11, 13)])
+        );
+
+        let lv = LineView::new("2020-06-22 11:00:00	osse	hg only").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::NoMatch);
+    }
+
+    #[test]
+    fn test_nick_file() {
+        let list = parse_nick_list("# the team\nosse\n\n  roadie \n#alice\n");
+        assert_eq!(list.len(), 2);
+        assert!(list.contains("osse") && list.contains("roadie"));
+
+        let mut settings = Settings {
+            pattern_string: String::new(),
+            nick_list: Some(list),
+            ..Default::default()
+        };
+        let osse = LineView::new("2020-06-22 11:00:00	osse	hi").unwrap();
+        let roadie = LineView::new("2020-06-22 11:00:01	@roadie	hi").unwrap();
+        let alice = LineView::new("2020-06-22 11:00:02	alice	hi").unwrap();
+        assert_eq!(match_line(&settings, &osse), MatchType::MatchNick);
+        assert_eq!(match_line(&settings, &roadie), MatchType::MatchNick);
+        assert_eq!(match_line(&settings, &alice), MatchType::NoMatch);
+
+        settings.nickname = String::from("alice");
+        assert_eq!(match_line(&settings, &alice), MatchType::MatchNick);
+
+        settings.nickname = String::new();
+        settings.nick_list = Some(HashSet::new());
+        assert_eq!(match_line(&settings, &osse), MatchType::NoMatch);
+        assert!(check_settings(&settings).is_ok());
+    }
+
+    #[test]
+    fn test_match_line_words() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("git"),
+            fixed: true,
+            word: true,
+            ..Default::default()
+        });
+
+        let lv = LineView::new("2020-06-22 11:00:00	osse	git, gitk and my-git/").unwrap();
+        assert_eq!(
+            match_line(&settings, &lv),
+            MatchType::Match(vec![(0, 3), (17, 20)])
+        );
+
+        settings.word_chars = String::from("-/");
+        settings = compiled(settings);
+        assert_eq!(match_line(&settings, &lv), MatchType::Match(vec![(0, 3)]));
+
+        let lv = LineView::new("2020-06-22 11:00:00	osse	ægit gitæ").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::NoMatch);
+    }
+
+    #[test]
+    fn test_match_line_fields() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("rust"),
+            fixed: true,
+            fields: Some(FieldRegex::parse("nick:/^a/ msg:/!$/").unwrap()),
+            ..Default::default()
+        });
+
+        let lv = LineView::new("2020-06-22 11:00:00	alice	rust!").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::Match(vec![(0, 4)]));
+
+        let lv = LineView::new("2020-06-22 11:00:00	alice	rust?").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::NoMatch);
+
+        let lv = LineView::new("2020-06-22 11:00:00	bob	rust!").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::NoMatch);
+    }
+
+    #[test]
+    fn test_match_line_invert() {
+        let bot = LineView::new("2020-06-22 11:00:00	bot	build passed").unwrap();
+        let alice = LineView::new("2020-06-22 11:00:00	alice	build broke").unwrap();
+        let bob = LineView::new("2020-06-22 11:00:00	bob	lunch?").unwrap();
+
+        let mut settings = compiled(Settings {
+            nickname: String::from("bot"),
+            invert_nick: true,
+            pattern_string: String::from("build"),
+            fixed: true,
+            ..Default::default()
+        });
+        assert_eq!(match_line(&settings, &bot), MatchType::NoMatch);
+        assert_eq!(
+            match_line(&settings, &alice),
+            MatchType::Match(vec![(0, 5)])
+        );
+        assert_eq!(match_line(&settings, &bob), MatchType::NoMatch);
+
+        settings.invert = true;
+        assert_eq!(match_line(&settings, &bot), MatchType::NoMatch);
+        assert_eq!(match_line(&settings, &alice), MatchType::NoMatch);
+        assert_eq!(match_line(&settings, &bob), MatchType::MatchNick);
+
+        settings.invert_nick = false;
+        settings.or = true;
+        assert_eq!(match_line(&settings, &bot), MatchType::MatchNick);
+        assert_eq!(match_line(&settings, &alice), MatchType::NoMatch);
+        assert_eq!(match_line(&settings, &bob), MatchType::MatchNick);
+
+        // With no pattern -v inverts the nick instead.
+        let mut settings = compiled(Settings {
+            nickname: String::from("bot"),
+            invert: true,
+            ..Default::default()
+        });
+        assert_eq!(match_line(&settings, &bot), MatchType::NoMatch);
+        assert_eq!(match_line(&settings, &alice), MatchType::MatchNick);
+        assert_eq!(match_line(&settings, &bob), MatchType::MatchNick);
+
+        settings.invert_nick = true;
+        assert_eq!(match_line(&settings, &bot), MatchType::MatchNick);
+        assert_eq!(match_line(&settings, &alice), MatchType::NoMatch);
+    }
+
+    #[test]
+    fn test_match_line_mention() {
+        let mut settings = compiled(Settings {
+            nickname: String::from("alice"),
+            mention: Some(String::from("bob")),
+            pattern_string: String::new(),
+            ..Default::default()
+        });
+
+        let lv = LineView::new("2020-06-22 11:00:00	alice	Bob: did you see bob_'s fix? thanks bob")
+            .unwrap();
+        assert_eq!(
+            match_line(&settings, &lv),
+            MatchType::Match(vec![(0, 3), (36, 39)])
+        );
+
+        let lv = LineView::new("2020-06-22 11:00:00	alice	bobby and [bob] too").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::NoMatch);
+
+        let lv = LineView::new("2020-06-22 11:00:00	carol	bob: hi").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::NoMatch);
+
+        settings.pattern_string = String::from("fix");
+        settings.fixed = true;
+        settings = compiled(settings);
+        let lv = LineView::new("2020-06-22 11:00:00	alice	bob, the fix").unwrap();
+        assert_eq!(
+            match_line(&settings, &lv),
+            MatchType::Match(vec![(0, 3), (9, 12)])
+        );
+        let lv = LineView::new("2020-06-22 11:00:00	alice	bob, hi").unwrap();
+        assert_eq!(match_line(&settings, &lv), MatchType::NoMatch);
+
+        settings.nick_message = true;
+        let lv = LineView::new("2020-06-22 11:00:00	alice	bob, the fix").unwrap();
+        assert_eq!(
+            match_line(&settings, &lv),
+            MatchType::Match(vec![(7, 10), (16, 19)])
+        );
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        assert_eq!(
+            merge_ranges(vec![(5, 8), (0, 3), (2, 4), (8, 9)]),
+            vec![(0, 4), (5, 9)]
+        );
+        assert_eq!(merge_ranges(vec![(0, 10), (2, 3)]), vec![(0, 10)]);
+        assert_eq!(merge_ranges(Vec::new()), Vec::new());
+    }
+
+    #[test]
+    fn test_match_timestamp() {
+        let mut settings = Settings {
+            pattern_string: String::from(":00:00$"),
+            match_timestamp: true,
+            match_prefix: String::from("["),
+            match_suffix: String::from("]"),
+            ..Default::default()
+        };
+        validate_settings(&mut settings).unwrap();
+
+        let path = write_log(
+            "match-timestamp",
+            "2020-06-22 11:00:00	osse	on the hour :00:00\n\
+             2020-06-22 11:00:01	osse	:00:00\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11[:00:00]	osse	on the hour :00:00\n"
+        );
+
+        let mut out = Vec::new();
+        process_file_json(&settings, &path, &mut out).unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            v["timestamp_matches"][0]["byte_range"],
+            serde_json::json!([13, 19])
+        );
+        assert_eq!(v["matches"], serde_json::json!([]));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_split_nick_message() {
+        // "alice: hi alice"
+        assert_eq!(
+            split_nick_message(5, &[(0, 9), (10, 15)]),
+            (vec![(0, 5)], vec![(0, 2), (3, 8)])
+        );
+        assert_eq!(split_nick_message(5, &[(1, 3)]), (vec![(1, 3)], vec![]));
+        assert_eq!(split_nick_message(5, &[(5, 7)]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_match_line_empty_matches() {
+        let settings = |p: &str| Settings {
+            pattern_string: String::from(p),
+            query: CompiledQuery::regex(p).unwrap(),
+            ..Default::default()
+        };
+        let lv = LineView::new("2020-06-22 11:00:00	osse	bab").unwrap();
+
+        assert_eq!(
+            match_line(&settings("a*"), &lv),
+            MatchType::Match(vec![(1, 2)])
+        );
+        assert_eq!(match_line(&settings("x*"), &lv), MatchType::MatchNick);
+        assert_eq!(match_line(&settings("^"), &lv), MatchType::MatchNick);
+        assert_eq!(match_line(&settings(r"\b"), &lv), MatchType::MatchNick);
+        assert_eq!(match_line(&settings("x"), &lv), MatchType::NoMatch);
+
+        let empty = LineView::new("2020-06-22 11:00:00	osse	").unwrap();
+        assert_eq!(match_line(&settings(".*"), &empty), MatchType::MatchNick);
+    }
+
+    #[test]
+    fn test_match_line_many_matches() {
+        let mut settings = Settings::default();
+        settings.nickname = String::from("osse");
+        settings.fixed = true;
+        settings.pattern_string = String::from("re");
+        let settings = compiled(settings);
+
+        let line = "2020-06-22 11:18:46	osse	check-ignore is for diagnosing .gitignore issues. it doesn't really have an effect on the repo";
+        let lv = LineView::new(&line).unwrap();
+
+        let m = match_line(&settings, &lv);
+
+        let v = vec![(10, 12), (39, 41), (61, 63), (90, 92)];
+        assert_eq!(m, MatchType::Match(v));
+    }
+
+    fn write_log(name: &str, contents: &str) -> path::PathBuf {
+        let path = env::temp_dir().join(format!("ircgrep-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Compiles the patterns as validate_settings would, without checking
+    /// the rest of the settings.
+    fn compiled(mut settings: Settings) -> Settings {
+        settings.query = compile_query(&settings).unwrap();
+        settings
+    }
+
+    fn run(settings: &Settings, path: &path::PathBuf) -> String {
+        let mut out = Vec::new();
+        process_file(settings, path, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_print_line_many_matches() {
+        let line = "2020-06-22 11:18:46	osse	aXbXc";
+        let lv = LineView::new(line).unwrap();
+
+        let mut out = Vec::new();
+        print_line(&mut out, &Settings::default(), &lv, &[(1, 2), (3, 4)]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:18:46	osse	aXbXc\n"
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        let theme = Theme::default();
+        assert_eq!(highlight("match", &theme, 0), "\x1b[1;31mmatch\x1b[0m");
+        assert_eq!(
+            highlight("a\x1b[0mb", &theme, 0),
+            "\x1b[1;31ma\x1b[0m\x1b[0m\x1b[1;31mb\x1b[0m"
+        );
+        assert_eq!(
+            highlight("\x1b[32mgreen\x1b[m", &theme, 0),
+            "\x1b[32m\x1b[1;31mgreen\x1b[0m\x1b[m"
+        );
+        assert_eq!(highlight("a\x1b[", &theme, 0), "\x1b[1;31ma\x1b[0m\x1b[");
+
+        let mono = Theme::named("mono").unwrap();
+        assert_eq!(highlight("match", &mono, 0), "\x1b[1;7mmatch\x1b[0m");
+        assert!(Theme::named("neon").is_none());
+
+        // Several fixed strings take turns with the other colors.
+        assert_eq!(highlight("m", &theme, 1), "\x1b[1;32mm\x1b[0m");
+        assert_eq!(highlight("m", &theme, 5), "\x1b[1;32mm\x1b[0m");
+        assert_eq!(highlight("m", &mono, 3), "\x1b[1;7mm\x1b[0m");
+
+        let settings = compiled(Settings {
+            pattern_string: String::from("git"),
+            extra_patterns: vec![String::from("hg"), String::from("svn")],
+            fixed: true,
+            color: true,
+            ..Default::default()
+        });
+        let lv = LineView::new("2020-06-22 11:18:46	osse	hg or git").unwrap();
+        let m = match match_line(&settings, &lv) {
+            MatchType::Match(m) => m,
+            m => panic!("{:?}", m),
+        };
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &m).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("\x1b[1;32mhg\x1b[0m or \x1b[1;31mgit\x1b[0m"),
+            "{:?}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_highlight_mirc_codes() {
+        let line = "2020-06-22 11:18:46	osse	it's \x0304red\x03 now";
+        let lv = LineView::new(line).unwrap();
+        let settings = Settings {
+            color: true,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &[(3, 14)]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:18:46	osse	it'\x1b[1;31ms \x0304red\x03 n\x1b[0mow\n"
+        );
+    }
+
+    #[test]
+    fn test_mark_matches() {
+        let lv = LineView::new("2020-06-22 11:18:46	osse	hi hi").unwrap();
+        let mut settings = Settings {
+            match_prefix: String::from("[["),
+            match_suffix: String::from("]]"),
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &[(0, 2), (3, 5)]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:18:46	osse	[[hi]] [[hi]]\n"
+        );
+
+        settings.color = true;
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &[(0, 2)]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:18:46	osse	[[\x1b[1;31mhi\x1b[0m]] hi\n"
+        );
+
+        settings.color = false;
+        settings.match_prefix = String::from("<");
+        settings.match_suffix = String::new();
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &[(3, 5)]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:18:46	osse	hi <hi\n"
+        );
+    }
+
+    #[test]
+    fn test_highlight_nick() {
+        let mut settings = Settings {
+            nickname: String::from("osse"),
+            highlight_nick: true,
+            color: true,
+            ..Default::default()
+        };
+
+        let path = write_log(
+            "highlight-nick",
+            "2020-06-22 11:00:00	@osse	hi\n\
+             2020-06-22 11:00:01	roadie	osse: hi\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	\x1b[1;31mosse\x1b[0m	hi\n"
+        );
+
+        settings.color = false;
+        assert_eq!(run(&settings, &path), "2020-06-22 11:00:00	osse	hi\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Parses a command line the way `search` does.
+    fn parse(args: &[&str]) -> Result<Settings, IrcgrepError> {
+        parse_with_config("", args)
+    }
+
+    /// Like `parse` with `contents` in the config file.
+    fn parse_with_config(contents: &str, args: &[&str]) -> Result<Settings, IrcgrepError> {
+        let config = write_log("parse-config", contents);
+        let mut argv = vec!["ircgrep", "--config", config.to_str().unwrap()];
+        argv.extend(args);
+
+        let mut settings = Settings::default();
+        let matches = app().get_matches_from_safe(argv).unwrap();
+        let result = parse_args(&mut settings, &matches, Local::now());
+        fs::remove_file(config).unwrap();
+        result.map(|_| settings)
+    }
+
+    #[test]
+    fn test_highlight_nick_args() {
+        let settings = parse(&[
+            "-c",
+            "rust",
+            "-n",
+            "osse",
+            "--highlight-nick",
+            "--color",
+            "always",
+        ])
+        .unwrap();
+        assert_eq!(settings.pattern_string, "");
+
+        let path = write_log(
+            "highlight-nick-args",
+            "2020-06-22 11:00:00	osse	hi\n\
+             2020-06-22 11:00:01	roadie	osse: hi\n",
+        );
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	\x1b[1;31mosse\x1b[0m	hi\n"
+        );
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(parse(&["-c", "rust"]).unwrap().pattern_string, ".*");
+        assert_eq!(
+            parse(&["-c", "rust", "-n", "osse", "-e", "hi"])
+                .unwrap()
+                .pattern_string,
+            "hi"
+        );
+        assert!(parse(&["-c", "rust", "-e", ""]).is_err());
+    }
+
+    #[test]
+    fn test_config_switches() {
+        let config = "strip_joins = true\n\
+                      [channel.\"#rust\"]\n\
+                      skip_blank = true\n\
+                      render_actions = true\n";
+
+        let settings = parse_with_config(config, &["-c", "rust", "x"]).unwrap();
+        assert!(settings.strip_joins && settings.skip_blank && settings.render_actions);
+
+        let settings = parse_with_config(
+            config,
+            &[
+                "-c",
+                "rust",
+                "--no-strip-joins",
+                "--no-skip-blank",
+                "--no-render-actions",
+                "x",
+            ],
+        )
+        .unwrap();
+        assert!(!settings.strip_joins && !settings.skip_blank && !settings.render_actions);
+
+        // The last one given wins.
+        let settings = parse_with_config(config, &["-j", "--no-strip-joins", "x"]).unwrap();
+        assert!(!settings.strip_joins);
+        let settings = parse_with_config(config, &["--no-strip-joins", "-j", "x"]).unwrap();
+        assert!(settings.strip_joins);
+        let settings = parse(&["--no-content-only", "x"]).unwrap();
+        assert!(!settings.content_only);
+    }
+
+    #[test]
+    fn test_to_alone() {
+        let settings = parse(&["-c", "rust", "--to", "bob", "--color", "never"]).unwrap();
+        assert_eq!(settings.pattern_string, "");
+
+        let path = write_log(
+            "to-alone",
+            "2020-06-22 11:00:00	alice	bob: hi\n\
+             2020-06-22 11:00:01	osse	hi all\n\
+             2020-06-22 11:00:02	roadie	ask bob\n",
+        );
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	alice	bob: hi\n\
+             2020-06-22 11:00:02	roadie	ask bob\n"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_dim_context() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            context: 1,
+            dim_context: true,
+            color: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "dim-context",
+            "2020-06-22 11:00:00	osse	oh hi\n\
+             2020-06-22 11:00:01	roadie	bye\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "\x1b[2m2020-06-22 11:00:00	\x1b[0m\x1b[2mosse\x1b[0m\x1b[2m	\x1b[0m\x1b[2moh \x1b[0mhi\n\
+             \x1b[2m2020-06-22 11:00:01	roadie	bye\x1b[0m\n"
+        );
+
+        settings.color = false;
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	osse	oh hi\n2020-06-22 11:00:01	roadie	bye\n"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_fit_message() {
+        let msg = "abcdefghij";
+
+        assert_eq!(
+            fit_message(msg, &[(2, 6)], 4, Fit::Wrap),
+            vec![
+                ("abcd", vec![(2, 4)], false),
+                ("efgh", vec![(0, 2)], false),
+                ("ij", vec![], false)
+            ]
+        );
+        assert_eq!(
+            fit_message(msg, &[(2, 6)], 4, Fit::Truncate),
+            vec![("abc", vec![(2, 3)], true)]
+        );
+        assert_eq!(
+            fit_message("abcd", &[], 4, Fit::Truncate),
+            vec![("abcd", vec![], false)]
+        );
+        assert_eq!(
+            fit_message("æøåæøå", &[(2, 6)], 4, Fit::Wrap),
+            vec![("æøåæ", vec![(2, 6)], false), ("øå", vec![], false)]
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut settings = Settings {
+            pattern_string: String::from(r"(\w+)@(\w+)\.com"),
+            query: CompiledQuery::regex(r"(\w+)@(\w+)\.com").unwrap(),
+            replace: Some(String::from("$1 at ${2}")),
+            ..Default::default()
+        };
+
+        let lv =
+            LineView::new("2020-06-22 11:00:00	osse	mail me@example.com or you@test.com").unwrap();
+        let m = match match_line(&settings, &lv) {
+            MatchType::Match(m) => m,
+            m => panic!("{:?}", m),
+        };
+
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &m).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:00:00	osse	mail me at example or you at test\n"
+        );
+
+        settings.fixed = true;
+        settings.query = CompiledQuery::fixed("a");
+        settings.replace = Some(String::from("[$1]"));
+        assert_eq!(
+            replace_matches(&settings, "a b a", &[(0, 1), (4, 5)], "[$1]"),
+            "[$1] b [$1]"
+        );
+
+        // -w finds "b c" after passing over "a b", which isn't a whole word.
+        let settings = compiled(Settings {
+            pattern_string: String::from("a b|b c"),
+            word: true,
+            replace: Some(String::from("X")),
+            ..Default::default()
+        });
+        let lv = LineView::new("2020-06-22 11:00:00	osse	xa b c").unwrap();
+        let m = match match_line(&settings, &lv) {
+            MatchType::Match(m) => m,
+            m => panic!("{:?}", m),
+        };
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &m).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:00:00	osse	xa X\n"
+        );
+    }
+
+    #[test]
+    fn test_render_actions() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("waves"),
+            fixed: true,
+            render_actions: true,
+            match_prefix: String::from("["),
+            match_suffix: String::from("]"),
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "actions",
+            "2020-06-22 11:00:00	 *	roadie waves\n\
+             2020-06-22 11:00:01	osse	waves back\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	* roadie [waves]\n\
+             2020-06-22 11:00:01	osse	[waves] back\n"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_snippet() {
+        let msg = "the quick brown fox jumps over the lazy dog";
+
+        assert_eq!(
+            snippet(msg, &[(16, 19)], 3),
+            (String::from("…wn fox ju…"), vec![(6, 9)])
+        );
+        assert_eq!(
+            snippet(msg, &[(4, 9), (10, 15)], 2),
+            (String::from("…e quick brown f…"), vec![(5, 10), (11, 16)])
+        );
+        assert_eq!(
+            snippet(msg, &[(0, 3), (40, 43)], 2),
+            (String::from("the q…y dog"), vec![(0, 3), (10, 13)])
+        );
+        assert_eq!(
+            snippet("æææ hi æææ", &[(7, 9)], 2),
+            (String::from("…æ hi æ…"), vec![(6, 8)])
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let lv =
+            LineView::new("2020-06-22 11:18:46	osse	hi hi hi hi hi hi hi hi hi hi hi hi").unwrap();
+        let settings = Settings {
+            fit: Some(Fit::Wrap),
+            width: 52,
+            match_prefix: String::from("["),
+            match_suffix: String::from("]"),
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        print_line(&mut out, &settings, &lv, &[(18, 23)]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2020-06-22 11:18:46	osse	hi hi hi hi hi hi [hi]\n\
+             \x20                               [ hi] hi hi hi hi\n"
+        );
+    }
+
+    #[test]
+    fn test_context() {
+        let settings = compiled(Settings {
+            fixed: true,
+            pattern_string: String::from("needle"),
+            context: 2,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "context",
+            "2020-06-22 11:00:00	a	one\n\
+             2020-06-22 11:00:01	b	two\n\
+             2020-06-22 11:00:02	c	three\n\
+             2020-06-22 11:00:03	d	a needle\n\
+             2020-06-22 11:00:04	e	four\n\
+             2020-06-22 11:00:05	f	five\n\
+             2020-06-22 11:00:06	g	six\n\
+             2020-06-22 11:00:07	h	another needle\n\
+             2020-06-22 11:00:08	i	seven\n\
+             2020-06-22 11:00:09	j	eight\n\
+             2020-06-22 11:00:10	k	nine\n\
+             2020-06-22 11:00:11	l	ten\n\
+             2020-06-22 11:00:12	m	eleven\n\
+             2020-06-22 11:00:13	n	last needle\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:01	b	two\n\
+             2020-06-22 11:00:02	c	three\n\
+             2020-06-22 11:00:03	d	a needle\n\
+             2020-06-22 11:00:04	e	four\n\
+             2020-06-22 11:00:05	f	five\n\
+             2020-06-22 11:00:06	g	six\n\
+             2020-06-22 11:00:07	h	another needle\n\
+             2020-06-22 11:00:08	i	seven\n\
+             2020-06-22 11:00:09	j	eight\n\
+             --\n\
+             2020-06-22 11:00:11	l	ten\n\
+             2020-06-22 11:00:12	m	eleven\n\
+             2020-06-22 11:00:13	n	last needle\n"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_context_matches() {
+        let mut settings = Settings {
+            nickname: String::from("osse"),
+            pattern_string: String::new(),
+            context: 1,
+            context_matches: true,
+            ..Default::default()
+        };
+
+        let path = write_log(
+            "context-matches",
+            "2020-06-22 11:00:00	roadie	anyone here?\n\
+             2020-06-22 11:00:01	-->	alice (~a@example.com) has joined #git\n\
+             2020-06-22 11:00:02	<--	bob (~b@example.com) has quit (bye)\n\
+             2020-06-22 11:00:03	osse	yes\n\
+             2020-06-22 11:00:04	--	carol is now known as carol_\n\
+             2020-06-22 11:00:05	roadie	great\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	roadie	anyone here?\n\
+             2020-06-22 11:00:03	osse	yes\n\
+             2020-06-22 11:00:05	roadie	great\n"
+        );
+
+        settings.context_matches = false;
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:02	<--	bob (~b@example.com) has quit (bye)\n\
+             2020-06-22 11:00:03	osse	yes\n\
+             2020-06-22 11:00:04	--	carol is now known as carol_\n"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ignore_bots() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("build"),
+            fixed: true,
+            bots: Some(Regex::new(BOT_PATTERN).unwrap()),
+            ..Default::default()
+        });
+
+        let m = |settings: &Settings, line| match_line(settings, &LineView::new(line).unwrap());
+        assert_eq!(
+            m(&settings, "2020-06-22 11:00:00	ci-bot	build failed"),
+            MatchType::Skip
+        );
+        assert_eq!(
+            m(&settings, "2020-06-22 11:00:00	NickServ	build"),
+            MatchType::Skip
+        );
+        assert_eq!(
+            m(&settings, "2020-06-22 11:00:00	botanist	build"),
+            MatchType::Match(vec![(0, 5)])
+        );
+
+        settings.bots = Some(Regex::new("^ci-").unwrap());
+        assert_eq!(
+            m(&settings, "2020-06-22 11:00:00	NickServ	build"),
+            MatchType::Match(vec![(0, 5)])
+        );
+        assert_eq!(
+            m(&settings, "2020-06-22 11:00:00	ci-runner	build"),
+            MatchType::Skip
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_read_error() {
+        let settings = Settings {
+            pattern_string: String::from("rust"),
+            no_messages: true,
+            ..Default::default()
+        };
+
+        assert_eq!(run(&settings, &env::temp_dir()), "");
+        assert_eq!(settings.errors.get(), 1);
+    }
+
+    #[test]
+    fn test_first_only() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("rust"),
+            fixed: true,
+            first_only: true,
+            strip_time_stamps: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "first-only",
+            "2020-06-22 11:00:00	osse	hi\n\
+             2020-06-22 11:00:01	osse	rust 1\n\
+             2020-06-22 11:00:02	roadie	rust 2\n\
+             2020-06-22 11:00:03	alice	rust 3\n",
+        );
+        assert_eq!(run(&settings, &path), "osse	rust 1\n");
+
+        settings.context = 1;
+        assert_eq!(
+            run(&settings, &path),
+            "osse	hi\nosse	rust 1\nroadie	rust 2\n"
+        );
+
+        let mut out = Vec::new();
+        assert_eq!(process_file_count(&settings, &path, &mut out).unwrap(), 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_squeeze_blank() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("^ *$"),
+            context: 1,
+            squeeze_blank: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "squeeze-blank",
+            "2020-06-22 11:00:00	osse	hi\n\
+             2020-06-22 11:00:01	osse	\n\
+             2020-06-22 11:00:02	osse	  \n\
+             2020-06-22 11:00:03	roadie	\n\
+             2020-06-22 11:00:04	osse	bye\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	osse	hi\n\
+             2020-06-22 11:00:01	osse	\n\
+             2020-06-22 11:00:04	osse	bye\n"
+        );
+
+        settings.squeeze_blank = false;
+        assert_eq!(run(&settings, &path).lines().count(), 5);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_nick_prefix() {
+        let mut settings = compiled(Settings {
+            nickname: String::from("@osse"),
+            pattern_string: String::new(),
+            keep_nick_prefix: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "nick-prefix",
+            "2020-06-22 11:00:00	@osse	as an op\n\
+             2020-06-22 11:00:01	osse	as myself\n\
+             2020-06-22 11:00:02	+roadie	voiced\n\
+             2020-06-22 11:00:03	roadie	not voiced\n",
+        );
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	@osse	as an op\n"
+        );
+
+        settings.nickname = String::new();
+        settings.pattern_string = String::from("o");
+        settings.fixed = true;
+        settings = compiled(settings);
+        settings.role = Some('+');
+        settings.strip_time_stamps = true;
+        assert_eq!(run(&settings, &path), "+roadie	voiced\n");
+
+        settings.keep_nick_prefix = false;
+        settings.role = Some('@');
+        assert_eq!(run(&settings, &path), "osse	as an op\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_byte_offset() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("needle"),
+            fixed: true,
+            byte_offset: true,
+            context: 1,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "byte-offset",
+            "2020-06-22 11:00:00	osse	hay\r\n\
+             2020-06-22 11:00:01	osse	needle\r\n\
+             2020-06-22 11:00:02	osse	hay\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "0-2020-06-22 11:00:00	osse	hay\n\
+             30:2020-06-22 11:00:01	osse	needle\n\
+             63-2020-06-22 11:00:02	osse	hay\n"
+        );
+
+        settings.context = 0;
+        settings.with_filename = true;
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        assert_eq!(
+            run(&settings, &path),
+            format!("{}:30:2020-06-22 11:00:01	osse	needle\n", name)
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_join_pastes() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("fn main"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "join-pastes",
+            "2020-06-22 11:00:00	osse	here's my code:\n\
+             2020-06-22 11:00:05	osse	fn\n\
+             2020-06-22 11:00:05	osse	main() {}\n\
+             2020-06-22 11:00:06	@osse	// it fails\n\
+             2020-06-22 11:00:06	roadie	fn\n\
+             2020-06-22 11:00:06	-->	main (~m@example.com) has joined #git\n\
+             2020-06-22 11:00:06	roadie	main\n\
+             2020-06-22 11:00:08	roadie	fn\n\
+             2020-06-22 11:00:10	roadie	main\n",
+        );
+
+        assert_eq!(run(&settings, &path), "");
+
+        settings.join_pastes = true;
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:05	osse	fn main() {} // it fails\n"
+        );
+
+        let lines = open_file(&settings, &path).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[2], "2020-06-22 11:00:06	roadie	fn");
+        assert_eq!(lines[4], "2020-06-22 11:00:06	roadie	main");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_multiline() {
+        let mut settings = Settings {
+            pattern_string: String::from(r"\?$\n.*yes"),
+            multiline: 2,
+            match_prefix: String::from("["),
+            match_suffix: String::from("]"),
+            ..Default::default()
+        };
+        settings.query = compile_query(&settings).unwrap();
+
+        let path = write_log(
+            "multiline",
+            "2020-06-22 11:00:00	roadie	anyone here?\n\
+             2020-06-22 11:00:01	osse	yes\n\
+             2020-06-22 11:00:02	roadie	is it?\n\
+             2020-06-22 11:00:03	osse	no\n\
+             2020-06-22 11:00:04	roadie	really?\n\
+             2020-06-22 11:00:05	osse	oh yes\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	roadie	anyone here[?]\n\
+             2020-06-22 11:00:01	osse	[yes]\n\
+             --\n\
+             2020-06-22 11:00:04	roadie	really[?]\n\
+             2020-06-22 11:00:05	osse	[oh yes]\n"
+        );
+
+        fs::remove_file(path).unwrap();
+
+        // Lines already printed for one match aren't printed again for the
+        // next.
+        settings.pattern_string = String::from(r"x\n.*x");
+        settings.query = compile_query(&settings).unwrap();
+        let path = write_log(
+            "multiline-overlap",
+            "2020-06-22 11:00:00	a	one x\n\
+             2020-06-22 11:00:01	b	x two x\n\
+             2020-06-22 11:00:02	c	x three\n",
+        );
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	a	one [x]\n\
+             2020-06-22 11:00:01	b	[x two x]\n\
+             2020-06-22 11:00:02	c	[x] three\n"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_with_filename() {
+        let mut settings = compiled(Settings {
+            fixed: true,
+            pattern_string: String::from("needle"),
+            context: 1,
+            with_filename: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "with-filename",
+            "2020-06-22 11:00:00	a	one\n\
+             2020-06-22 11:00:01	b	a needle\n",
+        );
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+        assert_eq!(
+            run(&settings, &path),
+            format!(
+                "{0}-2020-06-22 11:00:00	a	one\n\
+                 {0}:2020-06-22 11:00:01	b	a needle\n",
+                name
+            )
+        );
+
+        settings.with_filename = false;
+        settings.show_target = true;
+        assert_eq!(
+            line_prefix(
+                &settings,
+                path::Path::new("/logs/irc.libera.#git.weechatlog")
+            ),
+            Some(String::from("libera #git"))
+        );
+        assert_eq!(line_prefix(&settings, &path), Some(name));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_markers() {
+        let settings = compiled(Settings {
+            fixed: true,
+            pattern_string: String::from("needle"),
+            context: 1,
+            file_markers: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "markers.weechatlog",
+            "2020-06-22 23:59:59	a	one\n\
+             2020-06-23 00:00:00	b	a needle\n\
+             2020-06-23 00:00:01	c	two\n\
+             2020-06-23 00:00:02	d	three\n\
+             2020-06-23 00:00:03	e	another needle\n",
+        );
+        let miss = write_log("markers-miss", "2020-06-22 11:00:00	a	one\n");
+
+        assert_eq!(
+            run(&settings, &path),
+            format!(
+                "--- ircgrep-{}-markers [2020-06-22] ---\n\
+                 2020-06-22 23:59:59	a	one\n\
+                 2020-06-23 00:00:00	b	a needle\n\
+                 2020-06-23 00:00:01	c	two\n\
+                 2020-06-23 00:00:02	d	three\n\
+                 2020-06-23 00:00:03	e	another needle\n",
+                std::process::id()
+            )
+        );
+        assert_eq!(run(&settings, &miss), "");
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(miss).unwrap();
+    }
+
+    #[test]
+    fn test_empty_messages() {
+        let mut settings = Settings {
+            nickname: String::from("osse"),
+            pattern_string: String::from(".*"),
+            query: CompiledQuery::regex(".*").unwrap(),
+            context: 1,
+            ..Default::default()
+        };
+
+        let path = write_log(
+            "empty",
+            "2020-06-22 11:00:00	roadie	hi\n\
+             2020-06-22 11:00:01	osse	\n\
+             2020-06-22 11:00:02	osse	   \n\
+             2020-06-22 11:00:03	roadie	\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	roadie	hi\n\
+             2020-06-22 11:00:01	osse	\n\
+             2020-06-22 11:00:02	osse	   \n\
+             2020-06-22 11:00:03	roadie	\n"
+        );
+
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(":2\n"));
+
+        settings.skip_blank = true;
+        settings.context = 0;
+        assert_eq!(run(&settings, &path), "");
+
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(":0\n"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_display_name() {
+        let file = path::Path::new("/logs/2020/irc.libera.#git.weechatlog");
+        let name = |path_style| {
+            let settings = Settings {
+                path_style,
+                ..Default::default()
+            };
+            display_name(&settings, file).into_owned()
+        };
+
+        assert_eq!(name(PathStyle::Name), "irc.libera.#git.weechatlog");
+        assert_eq!(
+            name(PathStyle::Absolute),
+            "/logs/2020/irc.libera.#git.weechatlog"
+        );
+        assert_eq!(
+            name(PathStyle::Relative(path::PathBuf::from("/logs"))),
+            "2020/irc.libera.#git.weechatlog"
+        );
+        assert_eq!(
+            name(PathStyle::Relative(path::PathBuf::from("/elsewhere"))),
+            "/logs/2020/irc.libera.#git.weechatlog"
+        );
+    }
+
+    #[test]
+    fn test_count_full_path() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let path = write_log("full-path", "2020-06-22 11:00:00	roadie	hi\n");
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}:1\n", name));
+
+        settings.path_style = PathStyle::Absolute;
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{}:1\n", path.display())
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_count_color() {
+        // Even when `colored` would color everything, --count only does so
+        // if the output is colored.
+        colored::control::set_override(true);
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let path = write_log("count-color", "2020-06-22 11:00:00	roadie	hi\n");
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}:1\n", name));
+
+        let mut out = Vec::new();
+        process_file_churn(&settings, &path, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{}:joins=0 parts=0 quits=0\n", name)
+        );
+
+        settings.color = true;
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("\x1b[35m{}\x1b[0m\x1b[36m:\x1b[0m1\n", name)
+        );
+
+        settings.theme = Theme::named("mono").unwrap();
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("\x1b[1m{}\x1b[0m:1\n", name)
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_count_strip_joins() {
+        let mut settings = Settings {
+            pattern_string: String::from("roadie"),
+            fixed: true,
+            count: true,
+            strip_joins: true,
+            strip_time_stamps: true,
+            ..Default::default()
+        };
+        validate_settings(&mut settings).unwrap();
+
+        let path = write_log(
+            "count-joins",
+            "2020-06-22 11:00:00	-->	roadie (~user@example.com) has joined #git\n\
+             2020-06-22 11:00:01	osse	hi roadie\n\
+             2020-06-22 11:00:02	<--	roadie (~user@example.com) has quit (bye)\n",
+        );
+
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(":1\n"));
+
+        settings.strip_joins = false;
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(":3\n"));
+
+        settings.context = 2;
+        assert!(validate_settings(&mut settings).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_strip_timestamps() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            strip_time_stamps: true,
+            context: 1,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "strip-ts",
+            "2020-06-22 11:00:00	osse	hi\n\
+             2020-06-22 11:00:01	roadie	bye\n",
+        );
+
+        assert_eq!(run(&settings, &path), "osse	hi\nroadie	bye\n");
+
+        settings.nickname = String::from("roadie");
+        settings.or = true;
+        settings.context = 0;
+        assert_eq!(run(&settings, &path), "osse	hi\nroadie	bye\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_count_invert() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("rust"),
+            fixed: true,
+            invert: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "count-invert",
+            "2020-06-22 11:00:00	-->	roadie (~user@example.com) has joined #git\n\
+             2020-06-22 11:00:01	osse	rust is nice\n\
+             2020-06-22 11:00:02	roadie	what about c++\n\
+             2020-06-22 11:00:03	roadie	\n",
+        );
+
+        let count = |settings: &Settings| {
+            let mut out = Vec::new();
+            process_file_count(settings, &path, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            out.trim_end().rsplit(':').next().unwrap().to_string()
+        };
+        let printed = |settings: &Settings| run(settings, &path).lines().count().to_string();
+
+        assert_eq!(count(&settings), "3");
+        assert_eq!(printed(&settings), "3");
+
+        settings.strip_joins = true;
+        settings.skip_blank = true;
+        assert_eq!(count(&settings), "1");
+        assert_eq!(printed(&settings), "1");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_min_count() {
+        let mut settings = Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            count: true,
+            min_count: 2,
+            ..Default::default()
+        };
+        validate_settings(&mut settings).unwrap();
+
+        let few = write_log("min-count-1", "2020-06-22 11:00:00	roadie	hi\n");
+        let many = write_log(
+            "min-count-2",
+            "2020-06-22 11:00:00	roadie	hi\n\
+             2020-06-22 11:00:01	osse	hi\n",
+        );
+        let files = vec![few, many];
+
+        let mut out = Vec::new();
+        let total = sorted_counts(&settings, &files, SortBy::Date, &mut out).unwrap();
+        let name = files[1].file_name().unwrap().to_str().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}:2\n", name));
+        assert_eq!(total, 3);
+
+        let mut out = Vec::new();
+        assert_eq!(
+            process_file_count(&settings, &files[0], &mut out).unwrap(),
+            1
+        );
+        assert!(out.is_empty());
+
+        settings.count = false;
+        assert!(validate_settings(&mut settings).is_err());
+
+        let mut settings = Settings {
+            pattern_string: String::from("hi"),
+            count_to_stderr: true,
+            ..Default::default()
+        };
+        assert!(validate_settings(&mut settings).is_err());
+        settings.count = true;
+        validate_settings(&mut settings).unwrap();
+
+        for f in files {
+            fs::remove_file(f).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_count_percent() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            percent: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "percent",
+            "2020-06-22 11:00:00	roadie	hi hi\n\
+             2020-06-22 11:00:01	roadie	bye\n\
+             2020-06-22 11:00:02	roadie	bye\n",
+        );
+
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(":1 (33.3%)\n"));
+
+        let settings = compiled(Settings {
+            count_matches: true,
+            ..settings
+        });
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(":2 (33.3%)\n"));
+
+        let settings = compiled(Settings {
+            count_once: true,
+            ..settings
+        });
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(":1 (33.3%)\n"));
+
+        assert_eq!(percentage(0, 0), "0.0%");
+        assert_eq!(percentage(1, 1), "100.0%");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_log_file_pattern() {
+        for channel in &["rust", "#rust"] {
+            let p = log_file_pattern(".*", channel).unwrap();
+            assert!(p.is_match("irc.libera.#rust.weechatlog"));
+            assert!(!p.is_match("irc.libera.#rust-beginners.weechatlog"));
+            assert!(!p.is_match("irc.libera.#git.weechatlog"));
+        }
+
+        let p = log_file_pattern(".*", ".*").unwrap();
+        assert!(p.is_match("irc.libera.#rust.weechatlog"));
+        assert!(p.is_match("irc.oftc.##linux.weechatlog"));
+        assert!(!p.is_match("irc.server.libera.weechatlog.1"));
+    }
+
+    #[test]
+    fn test_use_color() {
+        assert!(use_color("always", true, || false));
+        assert!(!use_color("never", false, || true));
+        assert!(!use_color("auto", true, || true));
+        assert!(use_color("auto", false, || true));
+        assert!(!use_color("auto", false, || false));
+    }
+
+    #[test]
+    fn test_excluded_files() {
+        let settings = Settings {
+            exclude_channels: vec![String::from("#offtopic")],
+            exclude_networks: vec![String::from("oftc")],
+            ..Default::default()
+        };
+
+        let excluded = excluded_files(&settings).unwrap();
+        let is_excluded = |name| excluded.iter().any(|r| r.is_match(name));
+
+        assert!(is_excluded("irc.libera.#offtopic.weechatlog"));
+        assert!(is_excluded("irc.oftc.#debian.weechatlog"));
+        assert!(!is_excluded("irc.libera.#git.weechatlog"));
+        assert!(!is_excluded("irc.libera.#offtopic2.weechatlog"));
+        assert!(excluded_files(&Settings::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let secs = |v| parse_duration("--modified-since", v).map(|d| d.as_secs());
+
+        assert_eq!(secs("90m"), Ok(90 * 60));
+        assert_eq!(secs("24h"), Ok(24 * 60 * 60));
+        assert_eq!(secs("7d"), Ok(7 * 24 * 60 * 60));
+        assert_eq!(secs("2w"), Ok(14 * 24 * 60 * 60));
+        assert!(secs("7").is_err());
+        assert!(secs("d").is_err());
+        assert!(secs("7y").is_err());
+        assert!(secs("").is_err());
+        assert!(secs("-1d").is_err());
+        assert!(secs("99999999999999999w").is_err());
+        assert_eq!(secs("18446744073709551615s"), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn test_modified_since() {
+        let dir = env::temp_dir().join(format!("ircgrep-{}-mtime", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("irc.libera.#git.weechatlog");
+        let new = dir.join("irc.libera.#rust.weechatlog");
+
+        let day =
+            |n: u64| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(n * 24 * 60 * 60);
+        fs::File::create(&old)
+            .unwrap()
+            .set_modified(day(10))
+            .unwrap();
+        fs::File::create(&new)
+            .unwrap()
+            .set_modified(day(17))
+            .unwrap();
+
+        let mut settings = Settings {
+            network: String::from(".*"),
+            channel: String::from(".*"),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_log_files(&settings, &dir, day(18)).unwrap(),
+            vec![old.clone(), new.clone()]
+        );
+
+        settings.modified_since = parse_duration("", "7d").ok();
+        assert_eq!(
+            get_log_files(&settings, &dir, day(18)).unwrap(),
+            vec![new.clone()]
+        );
+        assert_eq!(
+            get_log_files(&settings, &dir, day(17)).unwrap(),
+            vec![old, new]
+        );
+        assert!(get_log_files(&settings, &dir, day(30)).unwrap().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_log_files() {
+        let dir = env::temp_dir().join(format!("ircgrep-{}-files", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = [
+            "irc.libera.#git.weechatlog",
+            "irc.libera.##rust.weechatlog",
+            "irc.oftc.#git.weechatlog",
+            "irc.libera.#git.txt",
+        ];
+        for name in &names {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let now = SystemTime::now();
+
+        let settings = Settings {
+            network: String::from("libera"),
+            channel: String::from(".*"),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_log_files(&settings, &dir, now).unwrap(),
+            vec![dir.join(names[1]), dir.join(names[0])]
+        );
+
+        let settings = Settings {
+            network: String::from(".*"),
+            channel: String::from("git"),
+            exclude_networks: vec![String::from("oftc")],
+            ..Default::default()
+        };
+        assert_eq!(
+            get_log_files(&settings, &dir, now).unwrap(),
+            vec![dir.join(names[0])]
+        );
+
+        assert!(get_log_files(&settings, &dir.join("missing"), now)
+            .unwrap()
+            .is_empty());
+
+        let nested = dir.join("libera").join("2020");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.join("libera").join(names[0]), "").unwrap();
+        fs::write(nested.join(names[0]), "").unwrap();
+        assert_eq!(get_log_files(&settings, &dir, now).unwrap().len(), 1);
+
+        let mut settings = Settings {
+            recursive: true,
+            ..settings
+        };
+        assert_eq!(get_log_files(&settings, &dir, now).unwrap().len(), 3);
+        settings.max_depth = Some(2);
+        assert_eq!(get_log_files(&settings, &dir, now).unwrap().len(), 2);
+        settings.max_depth = Some(1);
+        assert_eq!(get_log_files(&settings, &dir, now).unwrap().len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_log_files_by_date() {
+        let dir = env::temp_dir().join(format!("ircgrep-{}-dated", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = [
+            "irc.libera.#git.weechatlog",
+            "irc.libera.#git.2020-06-15.weechatlog",
+            "irc.libera.#git.2020-06-16.weechatlog",
+            "irc.libera.#git.2020-06-22.weechatlog",
+            "irc.libera.#git.2020-06-23.weechatlog",
+        ];
+        for name in &names {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let now = SystemTime::now();
+        let mut settings = Settings {
+            network: String::from(".*"),
+            channel: String::from(".*"),
+            ..Default::default()
+        };
+
+        // The last week ending on the 22nd starts on the 16th.
+        let (since, _) =
+            relative_range("last-week", NaiveDate::from_ymd_opt(2020, 6, 22).unwrap()).unwrap();
+        settings.since = Some(since.format("%Y-%m-%d").to_string());
+        assert_eq!(
+            get_log_files(&settings, &dir, now).unwrap(),
+            vec![
+                dir.join(names[0]),
+                dir.join(names[2]),
+                dir.join(names[3]),
+                dir.join(names[4])
+            ]
+        );
+
+        settings.since = Some(String::from("2020-06-16 12:00:00"));
+        settings.until = Some(String::from("2020-06-22"));
+        assert_eq!(
+            get_log_files(&settings, &dir, now).unwrap(),
+            vec![dir.join(names[0]), dir.join(names[2])]
+        );
+
+        settings.until = Some(String::from("2020-06-22 00:00:01"));
+        assert_eq!(get_log_files(&settings, &dir, now).unwrap().len(), 3);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // Elsewhere hard links aren't recognized as the same file.
+    #[cfg(unix)]
+    #[test]
+    fn test_unique_files() {
+        let dir = env::temp_dir().join(format!("ircgrep-{}-unique", std::process::id()));
+        let copies = dir.join("copies");
+        fs::create_dir_all(&copies).unwrap();
+        let log = dir.join("irc.libera.#git.weechatlog");
+        let link = dir.join("irc.libera.#rust.weechatlog");
+        let copy = copies.join("irc.libera.#git.weechatlog");
+        let other = dir.join("irc.oftc.#git.weechatlog");
+        fs::write(&log, "2020-06-22 11:00:00	osse	hi\n").unwrap();
+        fs::hard_link(&log, &link).unwrap();
+        fs::copy(&log, &copy).unwrap();
+        fs::write(&other, "2020-06-22 11:00:00	osse	bye\n").unwrap();
+
+        let files = vec![
+            log.clone(),
+            link.clone(),
+            copy.clone(),
+            log.clone(),
+            other.clone(),
+        ];
+        let mut settings = Settings::default();
+        assert_eq!(
+            unique_files(&settings, files.clone()),
+            vec![log.clone(), copy, other.clone()]
+        );
+
+        settings.dedup_files = true;
+        assert_eq!(unique_files(&settings, files), vec![log, other]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_target() {
+        let target = |p: &str| file_target(path::Path::new(p));
+
+        assert_eq!(
+            target("/logs/irc.libera.#rust.weechatlog"),
+            Some((String::from("libera"), String::from("#rust")))
+        );
+        assert_eq!(
+            target("irc.oftc.##c++.weechatlog"),
+            Some((String::from("oftc"), String::from("##c++")))
+        );
+        assert_eq!(target("/logs/core.weechat.weechatlog"), None);
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let settings = Settings {
+            no_messages: true,
+            ..Default::default()
+        };
+        let log = write_log("files-from", "");
+        let list = format!(
+            "{}\n\n/does/not/exist.weechatlog\n{}\r\n",
+            log.display(),
+            log.display()
+        );
+
+        assert_eq!(
+            read_file_list(&settings, list.as_bytes()),
+            vec![log.clone(), log.clone()]
+        );
+        assert!(read_file_list(&settings, &b""[..]).is_empty());
+
+        fs::remove_file(log).unwrap();
+    }
+
+    #[test]
+    fn test_warn_counts_errors() {
+        let settings = Settings {
+            no_messages: true,
+            ..Default::default()
+        };
+        let missing = env::temp_dir().join(format!("ircgrep-{}-missing", std::process::id()));
+
+        assert_eq!(run(&settings, &missing), "");
+        assert_eq!(settings.errors.get(), 1);
+        assert!(read_file_list(&settings, &b"/does/not/exist\n"[..]).is_empty());
+        assert_eq!(settings.errors.get(), 2);
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let (network, channel) = parse_target("irc.libera.#rust").unwrap();
+        assert_eq!((network.as_str(), channel.as_str()), ("libera", "rust"));
+
+        let (network, channel) = parse_target("irc.oftc.##c++.weechatlog").unwrap();
+        let p = log_file_pattern(&network, &channel).unwrap();
+        assert!(p.is_match("irc.oftc.##c++.weechatlog"));
+        assert!(!p.is_match("irc.oftc.##cc.weechatlog"));
+
+        assert_eq!(parse_target("libera.#rust"), None);
+        assert_eq!(parse_target("irc.libera"), None);
+        assert_eq!(parse_target("irc..#rust"), None);
+        assert_eq!(parse_target("irc.libera.#"), None);
+    }
+
+    #[test]
+    fn test_sort_log_files() {
+        let mut files = vec![
+            path::PathBuf::from("logs/irc.libera.#zsh.2020-06-02.weechatlog"),
+            path::PathBuf::from("logs/irc.libera.#git.2020-06-03.weechatlog"),
+            path::PathBuf::from("old/irc.libera.#git.2020-06-01.weechatlog"),
+            path::PathBuf::from("logs/irc.libera.#git.weechatlog"),
+            path::PathBuf::from("logs/irc.libera.#git.2020-06-02.weechatlog"),
+        ];
+        sort_log_files(&mut files);
+
+        assert_eq!(
+            files,
+            vec![
+                path::PathBuf::from("logs/irc.libera.#git.weechatlog"),
+                path::PathBuf::from("old/irc.libera.#git.2020-06-01.weechatlog"),
+                path::PathBuf::from("logs/irc.libera.#git.2020-06-02.weechatlog"),
+                path::PathBuf::from("logs/irc.libera.#zsh.2020-06-02.weechatlog"),
+                path::PathBuf::from("logs/irc.libera.#git.2020-06-03.weechatlog"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sorted_counts() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let b = write_log("sort-b", "2020-06-22 11:00:00	roadie	hi\n");
+        let a = write_log(
+            "sort-a",
+            "2020-06-23 11:00:00	roadie	hi\n\
+             2020-06-23 11:00:01	roadie	hi\n",
+        );
+        let c = write_log("sort-c", "2020-06-24 11:00:00	roadie	bye\n");
+        let files = vec![b, a, c];
+        let names = files
+            .iter()
+            .map(|f| f.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+
+        let sorted = |by| {
+            let mut out = Vec::new();
+            assert_eq!(sorted_counts(&settings, &files, by, &mut out).unwrap(), 3);
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            sorted(SortBy::Count),
+            format!("{}:2\n{}:1\n{}:0\n", names[1], names[0], names[2])
+        );
+        assert_eq!(
+            sorted(SortBy::Name),
+            format!("{}:2\n{}:1\n{}:0\n", names[1], names[0], names[2])
+        );
+        assert_eq!(
+            sorted(SortBy::Date),
+            format!("{}:1\n{}:2\n{}:0\n", names[0], names[1], names[2])
+        );
+
+        for f in files {
+            fs::remove_file(f).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_group() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            group: true,
+            ..Default::default()
+        });
+
+        let hit = write_log("group-hit", "2020-06-22 11:00:00	roadie	hi\n");
+        let miss = write_log("group-miss", "2020-06-22 11:00:00	roadie	bye\n");
+
+        let mut out = Vec::new();
+        process_file_grouped(&settings, &hit, false, &mut out).unwrap();
+        process_file_grouped(&settings, &miss, true, &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "==> {} <==\n2020-06-22 11:00:00	roadie	hi\n",
+                hit.file_name().unwrap().to_str().unwrap()
+            )
+        );
+
+        fs::remove_file(hit).unwrap();
+        fs::remove_file(miss).unwrap();
+    }
+
+    #[test]
+    fn test_group_separator() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            group: true,
+            group_separator: Some(String::new()),
+            ..Default::default()
+        });
+
+        let hit = write_log("group-sep", "2020-06-22 11:00:00	roadie	hi\n");
+        let name = hit.file_name().unwrap().to_str().unwrap();
+        let group = format!("==> {} <==\n2020-06-22 11:00:00	roadie	hi\n", name);
+        let grouped = |settings: &Settings| {
+            let mut out = Vec::new();
+            process_file_grouped(settings, &hit, false, &mut out).unwrap();
+            process_file_grouped(settings, &hit, true, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(grouped(&settings), format!("{}\n{}", group, group));
+
+        settings.group_separator = Some(String::from("----"));
+        assert_eq!(grouped(&settings), format!("{}----\n{}", group, group));
+
+        settings.group_separator = None;
+        assert_eq!(grouped(&settings), format!("{}{}", group, group));
+
+        fs::remove_file(hit).unwrap();
+    }
+
+    #[test]
+    fn test_collect_hits() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "hits",
+            "2020-06-22 11:00:00	roadie	hi\n\
+             2020-06-22 11:00:01	osse	bye\n\
+             2020-06-22 11:00:02	osse	oh hi\n\
+             2020-06-22 11:00:03	roadie	hi\n",
+        );
+
+        let hits = collect_hits(&settings, std::slice::from_ref(&path));
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[1].offset, 59);
+        assert_eq!(hits[1].line.message, "oh hi");
+        assert_eq!(hits[1].message_matches, vec![(3, 5)]);
+
+        // The same hits as the normal output would have.
+        settings.dedup_window = 5;
+        assert_eq!(
+            collect_hits(&settings, std::slice::from_ref(&path)).len(),
+            2
+        );
+        settings.first_only = true;
+        assert_eq!(
+            collect_hits(&settings, std::slice::from_ref(&path)).len(),
+            1
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("buy"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "dedup",
+            "2020-06-22 11:00:00	spam	buy now\n\
+             2020-06-22 11:00:01	spam	buy now\n\
+             2020-06-22 11:00:02	osse	don't buy it\n\
+             2020-06-22 11:00:03	spam	buy now\n\
+             2020-06-22 11:00:04	osse	buy now\n",
+        );
+
+        assert_eq!(run(&settings, &path).lines().count(), 5);
+
+        settings.dedup_window = 1;
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	spam	buy now\n\
+             2020-06-22 11:00:02	osse	don't buy it\n\
+             2020-06-22 11:00:03	spam	buy now\n\
+             2020-06-22 11:00:04	osse	buy now\n"
+        );
+
+        settings.dedup_window = 3;
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00	spam	buy now\n\
+             2020-06-22 11:00:02	osse	don't buy it\n\
+             2020-06-22 11:00:04	osse	buy now\n"
+        );
+
+        let mut out = Vec::new();
+        assert_eq!(process_file_count(&settings, &path, &mut out).unwrap(), 3);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_json() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            json: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "json",
+            "2020-06-22 11:00:00	roadie	hi hi \"hi\"\n\
+             2020-06-22 11:00:01	osse	bye\n",
+        );
+
+        let json = |settings: &Settings| {
+            let mut out = Vec::new();
+            process_file_json(settings, &path, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let out = json(&settings);
+        let v: serde_json::Value = serde_json::from_str(out.trim_end()).unwrap();
+        assert_eq!(out.lines().count(), 1);
+        assert_eq!(v["file"], path.file_name().unwrap().to_str().unwrap());
+        assert_eq!(v["network"], serde_json::Value::Null);
+        assert_eq!(v["nick"], "roadie");
+        assert_eq!(v["message"], "hi hi \"hi\"");
+        assert_eq!(v["matches"][2]["byte_range"], serde_json::json!([7, 9]));
+        assert_eq!(v["matches"][2]["char_range"], serde_json::json!([7, 9]));
+        assert_eq!(v["match_count"], 3);
+
+        settings.nickname = String::from("osse");
+        settings.pattern_string = String::new();
+        let v: serde_json::Value = serde_json::from_str(json(&settings).trim_end()).unwrap();
+        assert_eq!(v["matches"], serde_json::json!([]));
+        assert_eq!(v["match_count"], 1);
+
+        settings.nickname = String::new();
+        settings.pattern_string = String::from("hi");
+        settings.invert = true;
+        settings = compiled(settings);
+        let v: serde_json::Value = serde_json::from_str(json(&settings).trim_end()).unwrap();
+        assert_eq!(v["nick"], "osse");
+        assert_eq!(v["matches"], serde_json::json!([]));
+        assert_eq!(v["match_count"], 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let settings = Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            no_messages: true,
+            ..Default::default()
+        };
+
+        let path = env::temp_dir().join(format!("ircgrep-{}-missing", std::process::id()));
+        assert_eq!(open_file(&settings, &path).count(), 0);
+        assert_eq!(run(&settings, &path), "");
+    }
+
+    #[test]
+    fn test_json_array() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            json: true,
+            json_array: true,
+            ..Default::default()
+        });
+
+        let first = write_log("json-array-1", "2020-06-22 11:00:00	roadie	hi\n");
+        let second = write_log(
+            "json-array-2",
+            "2020-06-23 11:00:00	osse	hi\n\
+             2020-06-23 11:00:01	osse	bye\n",
+        );
+        let files = vec![first, second];
+
+        let mut out = Vec::new();
+        assert_eq!(json_array(&settings, &files, &mut out).unwrap(), 2);
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(v[0]["nick"], "roadie");
+        assert_eq!(v[1]["nick"], "osse");
+        assert_eq!(
+            v[2],
+            serde_json::json!({"summary": {"files": 2, "matches": 2}})
+        );
+
+        let mut out = Vec::new();
+        assert_eq!(json_array(&settings, &[], &mut out).unwrap(), 0);
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(v.as_array().unwrap().len(), 1);
+
+        for f in files {
+            fs::remove_file(f).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_json_ranges() {
+        assert_eq!(
+            json_ranges("blåbær hi", &[(0, 4), (4, 7), (9, 11)]),
+            serde_json::json!([
+                {"byte_range": [0, 4], "char_range": [0, 3]},
+                {"byte_range": [4, 7], "char_range": [3, 5]},
+                {"byte_range": [9, 11], "char_range": [7, 9]},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_list_files() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            list_files: true,
+            ..Default::default()
+        });
+
+        let hit = write_log("list-hit", "2020-06-22 11:00:00	roadie	hi\n");
+        let miss = write_log("list-miss", "2020-06-22 11:00:00	roadie	bye\n");
+
+        let list = |settings: &Settings| {
+            let mut out = Vec::new();
+            process_file_list(settings, &hit, &mut out).unwrap();
+            process_file_list(settings, &miss, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(list(&settings), format!("{}\n", hit.display()));
+
+        settings.null = true;
+        assert_eq!(list(&settings), format!("{}\0", hit.display()));
+
+        settings.null = false;
+        settings.quote = true;
+        assert_eq!(
+            list(&settings),
+            format!("{}\n", shell_quote(&hit.to_string_lossy()))
+        );
+
+        fs::remove_file(hit).unwrap();
+        fs::remove_file(miss).unwrap();
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(
+            shell_quote("/logs/irc.libera.rust.weechatlog"),
+            "/logs/irc.libera.rust.weechatlog"
+        );
+        assert_eq!(
+            shell_quote("/logs/irc.libera.#rust.weechatlog"),
+            "'/logs/irc.libera.#rust.weechatlog'"
+        );
+        assert_eq!(shell_quote("my logs/it's"), "'my logs/it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("rust"),
+            fixed: true,
+            summary_only: true,
+            ..Default::default()
+        });
+
+        let first = write_log(
+            "summary-1",
+            "2020-06-23 11:00:00	roadie	rust is nice\n\
+             2020-06-23 11:00:01	osse	what about c++\n\
+             2020-06-23 11:00:02	alice	rust rust rust\n",
+        );
+        let second = write_log(
+            "summary-2",
+            "2020-06-22 11:00:00	roadie	more rust\n\
+             2020-06-22 11:00:01	-->	rustacean (~r@example.com) has joined #rust\n\
+             2020-06-22 11:00:02	 *	alice likes rust\n",
+        );
+        let files = vec![first, second];
+
+        let mut out = Vec::new();
+        assert_eq!(summary(&settings, &files, &mut out).unwrap(), 5);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "5 matches from 2 nicks, 2020-06-22 to 2020-06-23\n"
+        );
+
+        settings.count_matches = true;
+        let mut out = Vec::new();
+        assert_eq!(summary(&settings, &files, &mut out).unwrap(), 8);
+
+        settings.pattern_string = String::from("python");
+        settings = compiled(settings);
+        let mut out = Vec::new();
+        summary(&settings, &files, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "0 matches from 0 nicks\n");
+
+        for f in files {
+            fs::remove_file(f).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unique_nicks() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("rust"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let first = write_log(
+            "unique-1",
+            "2020-06-22 11:00:00	roadie	rust is nice\n\
+             2020-06-22 11:00:01	osse	what about c++\n\
+             2020-06-22 11:00:02	@alice	rust rust rust\n",
+        );
+        let second = write_log(
+            "unique-2",
+            "2020-06-23 11:00:00	roadie	more rust\n\
+             2020-06-23 11:00:01	bob	rust!\n\
+             2020-06-23 11:00:02	-->	rustacean (~r@example.com) has joined #rust\n\
+             2020-06-23 11:00:03	 *	carol likes rust\n",
+        );
+        let files = vec![first, second];
+
+        let mut out = Vec::new();
+        assert_eq!(unique_nicks(&settings, &files, &mut out).unwrap(), 4);
+        assert_eq!(String::from_utf8(out).unwrap(), "4\n");
+
+        settings.list = true;
+        let mut out = Vec::new();
+        unique_nicks(&settings, &files, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "4\nalice\nbob\ncarol\nroadie\n"
+        );
+
+        let mut out = Vec::new();
+        assert_eq!(only_nicks(&settings, &files, &mut out).unwrap(), 4);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "alice\nbob\ncarol\nroadie\n"
+        );
+
+        for f in files {
+            fs::remove_file(f).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_only_nicks() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("osse"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "only-nicks",
+            "2020-06-22 11:00:00	-->	osse (~o@example.com) has joined #rust\n\
+             2020-06-22 11:00:01	 *	roadie waves at osse\n\
+             2020-06-22 11:00:02	--	osse is now known as osse_\n\
+             2020-06-22 11:00:03	alice	osse: hi\n\
+             2020-06-22 11:00:04	<--	osse (~o@example.com) has quit\n\
+             2020-06-22 11:00:05	=!=	osse: no such nick\n",
+        );
+        let files = vec![path];
+
+        let mut out = Vec::new();
+        assert_eq!(only_nicks(&settings, &files, &mut out).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "alice\nroadie\n");
+
+        fs::remove_file(&files[0]).unwrap();
+    }
+
+    #[test]
+    fn test_matrix() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("rust"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        let first = write_log(
+            "matrix-1",
+            "2020-06-22 11:00:00	roadie	rust is nice\n\
+             2020-06-22 11:00:01	osse	what about c++\n\
+             2020-06-22 11:00:02	@alice	rust rust rust\n\
+             2020-06-22 11:00:03	roadie	rust again\n",
+        );
+        let second = write_log(
+            "matrix-2",
+            "2020-06-23 11:00:00	roadie	more rust\n\
+             2020-06-23 11:00:01	a,b	rust!\n\
+             2020-06-23 11:00:02	-->	rustacean (~r@example.com) has joined #rust\n\
+             2020-06-23 11:00:03	 *	alice likes rust\n",
+        );
+        let files = vec![first, second];
+
+        let mut out = Vec::new();
+        assert_eq!(matrix(&settings, &files, &mut out).unwrap(), 7);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "date,\"a,b\",alice,roadie\n\
+             2020-06-22,0,1,2\n\
+             2020-06-23,1,1,1\n"
+        );
+
+        for f in files {
+            fs::remove_file(f).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let settings = compiled(Settings {
+            pattern_string: String::from("rust"),
+            fixed: true,
+            ..Default::default()
+        });
+
+        // 2020-06-22 was a Monday.
+        let log = write_log(
+            "heatmap",
+            "2020-06-22 11:00:00	roadie	rust is nice\n\
+             2020-06-22 11:59:59	osse	rust too\n\
+             2020-06-22 12:00:00	osse	what about c++\n\
+             2020-06-28 23:30:00	alice	rust on sunday\n\
+             garbage	alice	rust without a time\n",
+        );
+        let files = vec![log];
+
+        let mut out = Vec::new();
+        assert_eq!(
+            heatmap(&settings, &files, Heatmap::Csv, &mut out).unwrap(),
+            4
+        );
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 8);
+        assert!(lines[0].starts_with("day,0,1,2,"));
+        assert!(lines[0].ends_with(",22,23"));
+        assert_eq!(
+            lines[1],
+            format!("Mon{},2{}", ",0".repeat(11), ",0".repeat(12))
+        );
+        assert_eq!(lines[2], format!("Tue{}", ",0".repeat(24)));
+        assert_eq!(lines[7], format!("Sun{},1", ",0".repeat(23)));
+
+        let mut out = Vec::new();
+        heatmap(&settings, &files, Heatmap::Ascii, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "    0     6     12    18");
+        assert_eq!(
+            lines[1],
+            format!("Mon {}@{}", " ".repeat(11), " ".repeat(12))
+        );
+        assert_eq!(lines[7], format!("Sun {}+", " ".repeat(23)));
+
+        for f in files {
+            fs::remove_file(f).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_shade() {
+        assert_eq!(shade(0, 10), ' ');
+        assert_eq!(shade(1, 10), '.');
+        assert_eq!(shade(10, 10), '@');
+        assert_eq!(shade(1, 1), '@');
+        assert_eq!(shade(5, 100), '.');
+    }
+
+    #[test]
+    fn test_exec_args() {
+        let lines = vec![String::from("one"), String::from("two")];
+
+        assert_eq!(
+            exec_args(split_command("echo got {} !").unwrap(), &lines),
+            (
+                vec![
+                    String::from("echo"),
+                    String::from("got"),
+                    String::from("one"),
+                    String::from("two"),
+                    String::from("!")
+                ],
+                None
+            )
+        );
+        assert_eq!(
+            exec_args(split_command("wc -l").unwrap(), &lines),
+            (
+                vec![String::from("wc"), String::from("-l")],
+                Some(String::from("one\ntwo\n"))
+            )
+        );
+    }
+
+    #[test]
+    fn test_run_command() {
+        let lines = vec![String::from("hi")];
+        assert!(run_command("true", &lines).unwrap());
+        assert!(!run_command("false", &lines).unwrap());
+        assert!(run_command("grep -q hi", &lines).unwrap());
+        assert!(run_command("ircgrep-no-such-command", &lines).is_err());
+        assert!(run_command("grep -q 'h i", &lines).is_err());
+    }
+
+    #[test]
+    fn test_split_command() {
+        let words = |c| split_command(c).unwrap();
+
+        assert_eq!(words("  wc   -l "), vec!["wc", "-l"]);
+        assert_eq!(words("notify-send \"{}\""), vec!["notify-send", "{}"]);
+        assert_eq!(
+            words("printf '%s\\n' \"a \\\"b\\\" \\c\" d\\ e ''"),
+            vec!["printf", "%s\\n", "a \"b\" \\c", "d e", ""]
+        );
+        assert_eq!(words("x'y'\"z\""), vec!["xyz"]);
+        assert!(words("").is_empty());
+
+        assert!(split_command("echo 'hi").is_err());
+        assert!(split_command("echo \"hi").is_err());
+        assert!(split_command("echo hi\\").is_err());
+    }
+
+    #[test]
+    fn test_failed_command() {
+        let path = write_log(
+            "exec-fail",
+            "2020-06-22 11:00:00	osse	hi\n\
+             2020-06-22 11:00:01	osse	hi again\n",
+        );
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("hi"),
+            fixed: true,
+            exec: Some(String::from("false")),
+            no_messages: true,
+            ..Default::default()
+        });
+
+        assert_eq!(process_file_exec(&settings, &path).unwrap(), 2);
+        assert_eq!(settings.failed_commands.get(), 1);
+
+        settings.exec = Some(String::from("true"));
+        settings.failed_commands.set(0);
+        assert_eq!(process_file_exec(&settings, &path).unwrap(), 2);
+        assert_eq!(settings.failed_commands.get(), 0);
+    }
+
+    #[test]
+    fn test_churn() {
+        let settings = Settings::default();
+
+        let path = write_log(
+            "churn",
+            "2020-06-22 11:00:00	-->	roadie (~user@example.com) has joined #git\n\
+             2020-06-22 11:00:01	roadie	hi\n\
+             2020-06-22 11:00:02	<--	roadie (~user@example.com) has left #git\n\
+             2020-06-22 11:00:03	-->	roadie (~user@example.com) has joined #git\n\
+             2020-06-22 11:00:04	<--	roadie (~user@example.com) has quit (Quit: bye)\n",
+        );
+
+        let mut out = Vec::new();
+        assert_eq!(process_file_churn(&settings, &path, &mut out).unwrap(), 4);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with(":joins=2 parts=1 quits=1\n"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_validate_context() {
+        assert_eq!(parse_number("--context", "3"), Ok(3));
+        assert!(parse_number("--context", "three").is_err());
+        assert!(parse_number("--context", "-1").is_err());
+
+        let mut settings = Settings {
+            pattern_string: String::from("x"),
+            context: 3,
+            max_context: 10000,
+            ..Default::default()
+        };
+        validate_settings(&mut settings).unwrap();
+
+        settings.context = 100000000;
+        assert!(validate_settings(&mut settings).is_err());
+
+        settings.max_context = 100000000;
+        validate_settings(&mut settings).unwrap();
+    }
+
+    #[test]
+    fn test_validate_invalid_regex() {
+        let mut settings = Settings {
+            pattern_string: String::from("("),
+            ..Default::default()
+        };
+        assert!(validate_settings(&mut settings).is_err());
+    }
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter("|"), Some('|'));
+        assert_eq!(parse_delimiter("\\t"), Some('\t'));
+        assert_eq!(parse_delimiter("\t"), Some('\t'));
+        assert_eq!(parse_delimiter(""), None);
+        assert_eq!(parse_delimiter("||"), None);
+    }
+
+    #[test]
+    fn test_custom_delimiter() {
+        let settings = compiled(Settings {
+            nickname: String::from("osse"),
+            pattern_string: String::from("fine"),
+            fixed: true,
+            delimiter: Some(','),
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "delimiter",
+            "2020-06-22 11:00:00,osse,this is fine\n\
+             2020-06-22 11:00:01,roadie,this is fine too\n\
+             not a log line\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00,osse,this is fine\n"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_output_delimiter() {
+        let mut settings = compiled(Settings {
+            pattern_string: String::from("fine"),
+            fixed: true,
+            context: 1,
+            output_delimiter: Some(String::from(", ")),
+            ..Default::default()
+        });
+
+        let path = write_log(
+            "output-delimiter",
+            "2020-06-22 11:00:00	roadie	hi\n\
+             2020-06-22 11:00:01	osse	this is fine\n",
+        );
+
+        assert_eq!(
+            run(&settings, &path),
+            "2020-06-22 11:00:00, roadie, hi\n\
+             2020-06-22 11:00:01, osse, this is fine\n"
+        );
+
+        settings.count = true;
+        let mut out = Vec::new();
+        process_file_count(&settings, &path, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("output-delimiter, 1\n"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_log_dir() {
+        let env = |v: &str| match v {
+            "HOME" => Some(String::from("/home/osse")),
+            "USERPROFILE" => Some(String::from("C:\\Users\\osse")),
+            _ => None,
+        };
+
+        assert_eq!(
+            resolve_log_dir(None, env),
+            Some(path::PathBuf::from("/home/osse/.weechat/logs"))
+        );
+        assert_eq!(
+            resolve_log_dir(Some("/tmp/logs"), env),
+            Some(path::PathBuf::from("/tmp/logs"))
+        );
+    }
+
+    #[test]
+    fn test_log_dir_candidates() {
+        let env = |v: &str| match v {
+            "HOME" => Some(String::from("/home/osse")),
+            "WEECHAT_HOME" => Some(String::from("/srv/weechat")),
+            "XDG_DATA_HOME" => Some(String::from("/home/osse/data")),
+            _ => None,
+        };
+
+        assert_eq!(
+            log_dir_candidates(env),
+            vec![
+                path::PathBuf::from("/srv/weechat/logs"),
+                path::PathBuf::from("/home/osse/data/weechat/logs"),
+                path::PathBuf::from("/home/osse/.weechat/logs"),
+            ]
+        );
+
+        let env = |v: &str| match v {
+            "HOME" => Some(String::from("/home/osse")),
+            "XDG_DATA_HOME" => Some(String::new()),
+            _ => None,
+        };
+
+        assert_eq!(
+            log_dir_candidates(env),
+            vec![
+                path::PathBuf::from("/home/osse/.local/share/weechat/logs"),
+                path::PathBuf::from("/home/osse/.weechat/logs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_log_dir_without_home() {
+        let windows = |v: &str| match v {
+            "USERPROFILE" => Some(String::from("C:\\Users\\osse")),
+            _ => None,
+        };
+        let nothing = |_: &str| None;
 
-        assert_eq!(true, true);
+        assert_eq!(
+            resolve_log_dir(None, windows),
+            Some(
+                path::Path::new("C:\\Users\\osse")
+                    .join(".weechat")
+                    .join("logs")
+            )
+        );
+        assert_eq!(resolve_log_dir(None, nothing), None);
+        assert_eq!(
+            resolve_log_dir(Some("/tmp/logs"), nothing),
+            Some(path::PathBuf::from("/tmp/logs"))
+        );
     }
 }
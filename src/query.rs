@@ -0,0 +1,453 @@
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use memchr::memmem;
+use regex::Regex;
+
+/// The pattern made ready for searching, so that it's compiled once rather
+/// than for every line.
+#[derive(Debug)]
+pub struct CompiledQuery {
+    matcher: Matcher,
+    /// With -w, the characters that are part of a word besides letters,
+    /// digits and `_`.
+    word_chars: Option<String>,
+    /// With -w, the regex wrapped in a group with something that isn't part
+    /// of a word on either side.
+    word_regex: Option<Regex>,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Regex(Regex),
+    Fixed(memmem::Finder<'static>),
+    /// Several fixed strings, found in one pass over the line.
+    FixedSet(Box<AhoCorasick>),
+}
+
+/// Matches nothing. Settings start out with this until validate_settings
+/// compiles their pattern.
+impl Default for CompiledQuery {
+    fn default() -> CompiledQuery {
+        CompiledQuery::fixed_set::<&str>(&[])
+    }
+}
+
+/// Where the pattern was found in a string.
+#[derive(Debug, Default, PartialEq)]
+pub struct Found {
+    pub ranges: Vec<(usize, usize)>,
+    /// For each range, which of several fixed strings it is, in the order
+    /// they were given. A single pattern is always 0.
+    pub needles: Vec<usize>,
+    /// Whether the pattern matched the empty string somewhere. That's a match
+    /// but there's nothing to highlight, so it isn't in `ranges`.
+    pub empty: bool,
+}
+
+impl CompiledQuery {
+    pub fn regex(pattern: &str) -> Result<CompiledQuery, regex::Error> {
+        let regex = Regex::new(pattern)?;
+        Ok(CompiledQuery {
+            matcher: Matcher::Regex(regex),
+            word_chars: None,
+            word_regex: None,
+        })
+    }
+
+    pub fn fixed(pattern: &str) -> CompiledQuery {
+        CompiledQuery {
+            matcher: Matcher::Fixed(memmem::Finder::new(pattern).into_owned()),
+            word_chars: None,
+            word_regex: None,
+        }
+    }
+
+    /// Finds any of several fixed strings. Where they overlap the longest one
+    /// wins.
+    pub fn fixed_set<P: AsRef<str>>(patterns: &[P]) -> CompiledQuery {
+        let set = AhoCorasickBuilder::new()
+            .match_kind(MatchKind::LeftmostLongest)
+            // Takes longer to build and more memory, which a few -e
+            // patterns don't notice, for faster searching of every line.
+            .dfa(true)
+            .build(patterns.iter().map(|p| p.as_ref()));
+        CompiledQuery {
+            matcher: Matcher::FixedSet(Box::new(set)),
+            word_chars: None,
+            word_regex: None,
+        }
+    }
+
+    /// Only finds the pattern where it isn't part of a longer word, with
+    /// `word_chars` counting as part of words too. Where the first match
+    /// found isn't a whole word the search goes on for one that is, so `-w
+    /// 'ab|abc'` finds `abc`.
+    pub fn whole_words(self, word_chars: &str) -> Result<CompiledQuery, regex::Error> {
+        let word_regex = match &self.matcher {
+            Matcher::Regex(r) => {
+                // The same characters as `is_whole_word` counts as part of a
+                // word.
+                let not_word = format!(r"[^\p{{Alphabetic}}\p{{N}}_{}]", regex::escape(word_chars));
+                Some(Regex::new(&format!(
+                    "(?:^|{})({})(?:$|{})",
+                    not_word,
+                    r.as_str(),
+                    not_word
+                ))?)
+            }
+            Matcher::Fixed(_) | Matcher::FixedSet(_) => None,
+        };
+
+        Ok(CompiledQuery {
+            word_chars: Some(word_chars.to_string()),
+            word_regex,
+            ..self
+        })
+    }
+
+    /// Adds what --replace puts in place of the match at `s..e` in `text` to
+    /// `out`. For a regex `$1`, `${name}` and so on are what the pattern
+    /// captures there. Fixed strings capture nothing, so `replacement` is
+    /// used as is.
+    pub fn expand(&self, replacement: &str, text: &str, (s, e): (usize, usize), out: &mut String) {
+        match &self.matcher {
+            Matcher::Regex(r) => {
+                let groups = self
+                    .captures(r, text, s, e)
+                    .unwrap_or_else(|| vec![Some((s, e))]);
+                expand(replacement, r, text, &groups, out);
+            }
+            Matcher::Fixed(_) | Matcher::FixedSet(_) => out.push_str(replacement),
+        }
+    }
+
+    /// The groups of `re` when it matches exactly `s..e`, found by matching
+    /// again there with what's before `s` in view. With -w the match is the
+    /// group in `word_regex`, whose match starts with what's before the word.
+    /// Ranges that aren't one match, like mentions for --to, have no groups.
+    fn captures(
+        &self,
+        re: &Regex,
+        text: &str,
+        s: usize,
+        e: usize,
+    ) -> Option<Vec<Option<(usize, usize)>>> {
+        let (r, group, starts) = match &self.word_regex {
+            Some(w) => {
+                let before = text[..s]
+                    .chars()
+                    .next_back()
+                    .map_or(s, |c| s - c.len_utf8());
+                (w, 1, vec![before, s])
+            }
+            None => (re, 0, vec![s]),
+        };
+
+        let mut locs = r.capture_locations();
+        for start in starts {
+            if r.captures_read_at(&mut locs, text, start).is_some()
+                && locs.get(group) == Some((s, e))
+            {
+                return Some((group..locs.len()).map(|i| locs.get(i)).collect());
+            }
+        }
+
+        None
+    }
+
+    pub fn find(&self, haystack: &str) -> Found {
+        if let Some(chars) = &self.word_chars {
+            return self.find_words(chars, haystack);
+        }
+
+        let mut found = Found::default();
+
+        match &self.matcher {
+            Matcher::Regex(r) => {
+                for m in r.find_iter(haystack) {
+                    found.push(m.start(), m.end(), 0);
+                }
+            }
+            Matcher::Fixed(f) => {
+                let len = f.needle().len();
+                for pos in f.find_iter(haystack.as_bytes()) {
+                    found.push(pos, pos + len, 0);
+                }
+            }
+            Matcher::FixedSet(set) => {
+                for m in set.find_iter(haystack) {
+                    found.push(m.start(), m.end(), m.pattern());
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Which of several fixed strings `matched`, something found by `find`,
+    /// is, so that each can be highlighted in its own color. Anything else is
+    /// 0, like the matches of a single pattern.
+    pub fn needle(&self, matched: &str) -> usize {
+        if let Matcher::FixedSet(_) = self.matcher {
+            let found = self.find(matched);
+            if found.ranges == [(0, matched.len())] {
+                return found.needles[0];
+            }
+        }
+
+        0
+    }
+
+    /// Like `find` for -w. After a match that isn't a whole word the search
+    /// starts again one character on, rather than after the match, so that a
+    /// whole word overlapping it is still found.
+    fn find_words(&self, word_chars: &str, haystack: &str) -> Found {
+        let mut found = Found::default();
+        let mut pos = 0;
+
+        while let Some((s, e, needle)) = self.find_at(haystack, pos) {
+            let next = haystack[s..]
+                .chars()
+                .next()
+                .map_or(s + 1, |c| s + c.len_utf8());
+            if s == e {
+                found.empty = true;
+                pos = next;
+            } else if is_whole_word(word_chars, haystack, s, e) {
+                found.push(s, e, needle);
+                pos = e;
+            } else {
+                pos = next;
+            }
+
+            if pos > haystack.len() {
+                break;
+            }
+        }
+
+        found
+    }
+
+    /// The first match starting at `pos` or later, and which needle it is.
+    fn find_at(&self, haystack: &str, pos: usize) -> Option<(usize, usize, usize)> {
+        if let Some(r) = &self.word_regex {
+            // Whatever is around the word belongs to the match, so the word
+            // itself is the group. Searching on from the end of the group
+            // lets the next match start with what's after this one.
+            let mut locs = r.capture_locations();
+            r.captures_read_at(&mut locs, haystack, pos)?;
+            return locs.get(1).map(|(s, e)| (s, e, 0));
+        }
+
+        let rest = &haystack[pos..];
+        let (s, e, needle) = match &self.matcher {
+            Matcher::Regex(r) => r.find(rest).map(|m| (m.start(), m.end(), 0))?,
+            Matcher::Fixed(f) => f
+                .find(rest.as_bytes())
+                .map(|i| (i, i + f.needle().len(), 0))?,
+            Matcher::FixedSet(set) => set.find(rest).map(|m| (m.start(), m.end(), m.pattern()))?,
+        };
+        Some((pos + s, pos + e, needle))
+    }
+}
+
+impl Found {
+    fn push(&mut self, start: usize, end: usize, needle: usize) {
+        if start == end {
+            self.empty = true;
+        } else {
+            self.ranges.push((start, end));
+            self.needles.push(needle);
+        }
+    }
+}
+
+/// Like `regex::Captures::expand`, for groups found by `captures`:
+/// `$1`, `${1}`, `$name` and `${name}` are what the groups matched, with
+/// nothing for those that didn't, and `$$` is a `$`.
+fn expand(
+    replacement: &str,
+    re: &Regex,
+    text: &str,
+    groups: &[Option<(usize, usize)>],
+    out: &mut String,
+) {
+    let mut rest = replacement;
+
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(r) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = r;
+            continue;
+        }
+
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(r) => match r.find('}') {
+                Some(j) => (&r[..j], &r[j + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let j = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                (&rest[..j], &rest[j..])
+            }
+        };
+        // A `$` that doesn't refer to anything is kept.
+        if name.is_empty() {
+            out.push('$');
+            continue;
+        }
+
+        let index = match name.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => re.capture_names().position(|n| n == Some(name)),
+        };
+        if let Some((s, e)) = index.and_then(|i| groups.get(i).copied().flatten()) {
+            out.push_str(&text[s..e]);
+        }
+        rest = after;
+    }
+
+    out.push_str(rest);
+}
+
+/// Whether `text[start..end]` isn't part of a longer word.
+fn is_whole_word(word_chars: &str, text: &str, start: usize, end: usize) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || word_chars.contains(c);
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finding() {
+        let q = CompiledQuery::fixed("a.b");
+        assert_eq!(
+            q.find("a.b axb a.ba.b").ranges,
+            vec![(0, 3), (8, 11), (11, 14)]
+        );
+
+        let q = CompiledQuery::regex("a.b").unwrap();
+        assert_eq!(q.find("a.b axb").ranges, vec![(0, 3), (4, 7)]);
+
+        let q = CompiledQuery::regex("x*").unwrap();
+        assert_eq!(
+            q.find("axxb"),
+            Found {
+                ranges: vec![(1, 3)],
+                needles: vec![0],
+                empty: true
+            }
+        );
+
+        assert!(CompiledQuery::regex("(").is_err());
+    }
+
+    #[test]
+    fn replacing() {
+        let replace = |q: &CompiledQuery, text: &str, r| {
+            let mut out = String::new();
+            for m in q.find(text).ranges {
+                q.expand(r, text, m, &mut out);
+                out.push('|');
+            }
+            out
+        };
+
+        let q = CompiledQuery::regex(r"(?P<user>\w+)@(\w+)").unwrap();
+        assert_eq!(
+            replace(&q, "a@b c@d", "$2 ${user}$$ $3 ${2 $"),
+            "b a$  ${2 $|d c$  ${2 $|"
+        );
+        assert_eq!(replace(&q, "a@b", "$user_x ${user}_x"), " a_x|");
+
+        // The groups come from the match at that place, even when the
+        // pattern matches differently from the start of the line.
+        let q = CompiledQuery::regex("(a) (b)|(b) (c)")
+            .unwrap()
+            .whole_words("")
+            .unwrap();
+        assert_eq!(replace(&q, "xa b c", "$3$4"), "bc|");
+        let q = CompiledQuery::regex("(ab)|(abc)")
+            .unwrap()
+            .whole_words("")
+            .unwrap();
+        assert_eq!(replace(&q, "abc", "[$1$2]"), "[abc]|");
+
+        // A range that isn't a match, like a mention, is just the whole of it.
+        let mut out = String::new();
+        q.expand("<$0$1>", "hi bob", (3, 6), &mut out);
+        assert_eq!(out, "<bob>");
+
+        let q = CompiledQuery::fixed("a");
+        assert_eq!(replace(&q, "a b a", "[$1]"), "[$1]|[$1]|");
+    }
+
+    #[test]
+    fn fixed_set() {
+        let q = CompiledQuery::fixed_set(&["git", "gitk", "a.b"]);
+        assert_eq!(
+            q.find("gitk a.b axb git").ranges,
+            vec![(0, 4), (5, 8), (13, 16)]
+        );
+        assert!(q.find("hg").ranges.is_empty());
+
+        let q = CompiledQuery::fixed_set(&["gi", "it"]);
+        assert_eq!(q.find("git").ranges, vec![(0, 2)]);
+
+        let q = CompiledQuery::fixed_set(&["hg", "git", "gitk"]);
+        assert_eq!(q.find("git hg gitk").needles, vec![1, 0, 2]);
+        assert_eq!(q.needle("gitk"), 2);
+        assert_eq!(q.needle("hg"), 0);
+        assert_eq!(q.needle("git hg"), 0);
+        assert_eq!(CompiledQuery::fixed("git").needle("git"), 0);
+
+        let q = CompiledQuery::fixed_set(&["git", "hg"])
+            .whole_words("")
+            .unwrap();
+        assert_eq!(q.find("gitk hg").ranges, vec![(5, 7)]);
+    }
+
+    #[test]
+    fn whole_words() {
+        let q = CompiledQuery::fixed("git").whole_words("").unwrap();
+        assert_eq!(
+            q.find("git, gitk and my-git/").ranges,
+            vec![(0, 3), (17, 20)]
+        );
+
+        let q = CompiledQuery::regex("g.t")
+            .unwrap()
+            .whole_words("-/")
+            .unwrap();
+        assert_eq!(q.find("git, gitk and my-git/").ranges, vec![(0, 3)]);
+        assert!(q.find("ægit gitæ").ranges.is_empty());
+
+        // Whole words that only turn up by looking past a match that isn't.
+        let q = CompiledQuery::regex("ab|abc")
+            .unwrap()
+            .whole_words("")
+            .unwrap();
+        assert_eq!(q.find("abc").ranges, vec![(0, 3)]);
+        let q = CompiledQuery::regex("foo")
+            .unwrap()
+            .whole_words("")
+            .unwrap();
+        assert_eq!(q.find("foofoo foo foo").ranges, vec![(7, 10), (11, 14)]);
+        let q = CompiledQuery::fixed("aa").whole_words("").unwrap();
+        assert_eq!(q.find("aaa aa").ranges, vec![(4, 6)]);
+        let q = CompiledQuery::fixed_set(&["ab", "b"])
+            .whole_words("")
+            .unwrap();
+        assert_eq!(q.find("ab-b").ranges, vec![(0, 2), (3, 4)]);
+        let q = CompiledQuery::fixed("xab").whole_words("").unwrap();
+        assert_eq!(q.find("xxab xab").ranges, vec![(5, 8)]);
+    }
+}
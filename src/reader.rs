@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+/// What a log file is written in. Everything is turned into UTF-8 as it's
+/// read.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// ISO-8859-1, where every byte is the code point with the same value.
+    Latin1,
+    /// Windows-1252, which is Latin-1 with printable characters such as `€`
+    /// and curly quotes where Latin-1 has control codes.
+    Cp1252,
+}
+
+/// Reads a log file line by line with the line endings removed.
+///
+/// Lines that aren't valid UTF-8 are skipped, unless the reader is lossy in
+/// which case invalid sequences are replaced with U+FFFD. Any offsets into
+/// such a line are offsets into the replaced string, not into the file.
+/// Lines in other encodings are always valid, so nothing is skipped then.
+///
+/// An error reading the file, like trying to read a directory, is given
+/// instead of a line and ends the lines.
+pub struct LogReader {
+    inner: BufReader<File>,
+    lossy: bool,
+    encoding: Encoding,
+    buf: Vec<u8>,
+    /// How many bytes have been read from the file.
+    pos: u64,
+    /// Whether reading has failed, after which there's nothing more to read.
+    failed: bool,
+}
+
+impl LogReader {
+    pub fn new(file: File, lossy: bool) -> LogReader {
+        LogReader::from_reader(BufReader::new(file), lossy)
+    }
+
+    /// Like `new` but reads `capacity` bytes at a time.
+    pub fn with_capacity(capacity: usize, file: File, lossy: bool) -> LogReader {
+        LogReader::from_reader(BufReader::with_capacity(capacity, file), lossy)
+    }
+
+    fn from_reader(inner: BufReader<File>, lossy: bool) -> LogReader {
+        LogReader {
+            inner,
+            lossy,
+            encoding: Encoding::Utf8,
+            buf: Vec::new(),
+            pos: 0,
+            failed: false,
+        }
+    }
+
+    /// Makes the reader decode lines from `encoding` instead of UTF-8.
+    pub fn with_encoding(self, encoding: Encoding) -> LogReader {
+        LogReader { encoding, ..self }
+    }
+
+    /// Turns the reader into one that gives each line along with the byte
+    /// offset in the file where it starts.
+    pub fn with_offsets(mut self) -> impl Iterator<Item = io::Result<(u64, String)>> {
+        std::iter::from_fn(move || self.read_line())
+    }
+
+    fn read_line(&mut self) -> Option<io::Result<(u64, String)>> {
+        if self.failed {
+            return None;
+        }
+
+        loop {
+            self.buf.clear();
+
+            let start = self.pos;
+            match self.inner.read_until(b'\n', &mut self.buf) {
+                Ok(0) => return None,
+                Ok(n) => self.pos += n as u64,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+
+            if self.buf.ends_with(b"\n") {
+                self.buf.pop();
+            }
+            if self.buf.ends_with(b"\r") {
+                self.buf.pop();
+            }
+
+            match self.encoding {
+                Encoding::Utf8 => {}
+                Encoding::Latin1 => {
+                    return Some(Ok((start, self.buf.iter().map(|&b| b as char).collect())))
+                }
+                Encoding::Cp1252 => {
+                    let (s, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(&self.buf);
+                    return Some(Ok((start, s.into_owned())));
+                }
+            }
+
+            if self.lossy {
+                return Some(Ok((start, String::from_utf8_lossy(&self.buf).into_owned())));
+            }
+
+            if let Ok(s) = std::str::from_utf8(&self.buf) {
+                return Some(Ok((start, s.to_string())));
+            }
+        }
+    }
+}
+
+impl Iterator for LogReader {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        self.read_line().map(|r| r.map(|(_, line)| line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn invalid_utf8() {
+        let path = env::temp_dir().join(format!("ircgrep-{}-reader", std::process::id()));
+        fs::write(&path, b"first\r\nsecond \xe6\xf8\xe5\nthird\r").unwrap();
+
+        let strict = LogReader::new(File::open(&path).unwrap(), false);
+        assert_eq!(
+            strict.map(Result::unwrap).collect::<Vec<_>>(),
+            vec!["first", "third"]
+        );
+
+        let lossy = LogReader::with_capacity(4, File::open(&path).unwrap(), true);
+        assert_eq!(
+            lossy.map(Result::unwrap).collect::<Vec<_>>(),
+            vec!["first", "second \u{fffd}\u{fffd}\u{fffd}", "third"]
+        );
+
+        let offsets = LogReader::new(File::open(&path).unwrap(), false).with_offsets();
+        assert_eq!(
+            offsets.map(Result::unwrap).collect::<Vec<_>>(),
+            vec![(0, String::from("first")), (18, String::from("third"))]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn legacy_encodings() {
+        let path = env::temp_dir().join(format!("ircgrep-{}-encoding", std::process::id()));
+        fs::write(&path, b"bl\xe5b\xe6r\r\n\x80 \x93hi\x94\n").unwrap();
+
+        let utf8 = LogReader::new(File::open(&path).unwrap(), false);
+        assert_eq!(utf8.count(), 0);
+
+        let latin1 =
+            LogReader::new(File::open(&path).unwrap(), false).with_encoding(Encoding::Latin1);
+        assert_eq!(
+            latin1.map(Result::unwrap).collect::<Vec<_>>(),
+            vec!["blåbær", "\u{80} \u{93}hi\u{94}"]
+        );
+
+        let cp1252 =
+            LogReader::new(File::open(&path).unwrap(), false).with_encoding(Encoding::Cp1252);
+        assert_eq!(
+            cp1252.map(Result::unwrap).collect::<Vec<_>>(),
+            vec!["blåbær", "€ “hi”"]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn read_error() {
+        // On Unix a directory can be opened but not read.
+        let dir = File::open(env::temp_dir()).unwrap();
+        let mut reader = LogReader::new(dir, false);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,362 @@
+use crate::line_view::OwnedLine;
+use crate::reader::{Encoding, LogReader};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Attribute, Color, Print, SetAttribute, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Number of lines shown on either side of a match when it's expanded.
+const CONTEXT: usize = 5;
+
+/// A matching line and where in its nick and message the matches are.
+pub struct Hit {
+    pub file: PathBuf,
+    /// Where the line starts in the file, so that the context can be found
+    /// however many lines before it the search left out.
+    pub offset: u64,
+    pub line: OwnedLine,
+    pub timestamp_matches: Vec<(usize, usize)>,
+    pub nick_matches: Vec<(usize, usize)>,
+    pub message_matches: Vec<(usize, usize)>,
+}
+
+impl Hit {
+    /// The line with its fields separated by spaces and the matches moved
+    /// to fit.
+    fn text(&self) -> (String, Vec<(usize, usize)>) {
+        let l = &self.line;
+        let text = format!("{} {} {}", l.timestamp, l.nick, l.message);
+
+        let nick_start = l.timestamp.len() + 1;
+        let message_start = nick_start + l.nick.len() + 1;
+        let shift = |v: &[(usize, usize)], o| {
+            v.iter()
+                .map(move |&(s, e)| (s + o, e + o))
+                .collect::<Vec<_>>()
+        };
+
+        let mut matches = self.timestamp_matches.clone();
+        matches.extend(shift(&self.nick_matches, nick_start));
+        matches.extend(shift(&self.message_matches, message_start));
+
+        (text, matches)
+    }
+}
+
+struct Browser<'a> {
+    hits: &'a [Hit],
+    lossy: bool,
+    encoding: Encoding,
+    selected: usize,
+    top: usize,
+    expanded: bool,
+    files: HashMap<PathBuf, Vec<(u64, String)>>,
+}
+
+impl<'a> Browser<'a> {
+    fn new(hits: &'a [Hit], lossy: bool, encoding: Encoding) -> Browser<'a> {
+        Browser {
+            hits,
+            lossy,
+            encoding,
+            selected: 0,
+            top: 0,
+            expanded: false,
+            files: HashMap::new(),
+        }
+    }
+
+    fn down(&mut self) {
+        if self.selected + 1 < self.hits.len() {
+            self.selected += 1;
+        }
+    }
+
+    fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Moves to the first match in the next file.
+    fn next_file(&mut self) {
+        let file = &self.hits[self.selected].file;
+        if let Some(i) = self.hits[self.selected..]
+            .iter()
+            .position(|h| &h.file != file)
+        {
+            self.selected += i;
+        }
+    }
+
+    /// Moves to the first match in the current file, or in the previous file
+    /// if already there.
+    fn prev_file(&mut self) {
+        let start = self.file_start(self.selected);
+        self.selected = if start == self.selected && start > 0 {
+            self.file_start(start - 1)
+        } else {
+            start
+        };
+    }
+
+    fn file_start(&self, i: usize) -> usize {
+        let file = &self.hits[i].file;
+        self.hits[..i]
+            .iter()
+            .rposition(|h| &h.file != file)
+            .map_or(0, |p| p + 1)
+    }
+
+    /// The lines around the selected hit, each with whether it's the hit.
+    fn context(&mut self) -> io::Result<Vec<(bool, String)>> {
+        let hit = &self.hits[self.selected];
+
+        if !self.files.contains_key(&hit.file) {
+            let reader = LogReader::new(fs::File::open(&hit.file)?, self.lossy)
+                .with_encoding(self.encoding)
+                .with_offsets();
+            self.files
+                .insert(hit.file.clone(), reader.collect::<io::Result<_>>()?);
+        }
+
+        let lines = &self.files[&hit.file];
+        Ok(context_around(lines, hit.offset))
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+
+        let list_height = if self.expanded {
+            height.saturating_sub(1) / 2
+        } else {
+            height.saturating_sub(1)
+        }
+        .max(1);
+
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + list_height {
+            self.top = self.selected + 1 - list_height;
+        }
+
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+
+        let visible = self.hits.iter().enumerate().skip(self.top);
+        for (row, (i, hit)) in visible.take(list_height).enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            if i == self.selected {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            let (text, matches) = hit.text();
+            draw_text(out, &text, &matches, width)?;
+            queue!(out, SetAttribute(Attribute::Reset))?;
+        }
+
+        if self.expanded {
+            let context = self.context()?;
+            let rows = height.saturating_sub(1).saturating_sub(list_height);
+
+            for (row, (is_hit, line)) in context.iter().take(rows).enumerate() {
+                queue!(out, cursor::MoveTo(0, (list_height + row) as u16))?;
+                if *is_hit {
+                    queue!(out, SetAttribute(Attribute::Bold))?;
+                } else {
+                    queue!(out, SetAttribute(Attribute::Dim))?;
+                }
+                draw_text(out, line, &[], width)?;
+                queue!(out, SetAttribute(Attribute::Reset))?;
+            }
+        }
+
+        let status = format!(
+            "[{}/{}] {}  j/k: move  n/N: file  enter: context  q: quit",
+            self.selected + 1,
+            self.hits.len(),
+            self.hits[self.selected].file.display()
+        );
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            SetAttribute(Attribute::Reverse),
+        )?;
+        draw_text(out, &status, &[], width)?;
+        queue!(out, SetAttribute(Attribute::Reset))?;
+
+        out.flush()
+    }
+}
+
+/// Prints `text` cut off at `width` characters with the matches in red.
+fn draw_text(
+    out: &mut impl Write,
+    text: &str,
+    matches: &[(usize, usize)],
+    width: usize,
+) -> io::Result<()> {
+    let mut in_match = false;
+
+    for (n, (i, c)) in text.char_indices().enumerate() {
+        if n >= width {
+            break;
+        }
+
+        let m = matches.iter().any(|&(s, e)| s <= i && i < e);
+        if m != in_match {
+            if m {
+                queue!(out, SetForegroundColor(Color::Red))?;
+            } else {
+                queue!(out, SetForegroundColor(Color::Reset))?;
+            }
+            in_match = m;
+        }
+
+        queue!(out, Print(c))?;
+    }
+
+    queue!(out, SetForegroundColor(Color::Reset))
+}
+
+/// Puts the terminal back the way it was, even when bailing out with an error.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Shows the matches in a full screen list until the user quits.
+pub fn run(hits: &[Hit], lossy: bool, encoding: Encoding) -> io::Result<()> {
+    if hits.is_empty() {
+        return Ok(());
+    }
+
+    let mut browser = Browser::new(hits, lossy, encoding);
+    let _screen = Screen::enter()?;
+    let mut out = io::stdout();
+
+    loop {
+        browser.draw(&mut out)?;
+
+        let key = match event::read()? {
+            Event::Key(k) if k.kind == KeyEventKind::Press => k,
+            _ => continue,
+        };
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('j') | KeyCode::Down => browser.down(),
+            KeyCode::Char('k') | KeyCode::Up => browser.up(),
+            KeyCode::Char('n') => browser.next_file(),
+            KeyCode::Char('N') => browser.prev_file(),
+            KeyCode::Char('g') | KeyCode::Home => browser.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => browser.selected = hits.len() - 1,
+            KeyCode::Enter | KeyCode::Char(' ') => browser.expanded = !browser.expanded,
+            _ => {}
+        }
+    }
+}
+
+/// The line starting at `offset` and up to `CONTEXT` lines on either side of
+/// it, each with whether it's that line.
+fn context_around(lines: &[(u64, String)], offset: u64) -> Vec<(bool, String)> {
+    let at = lines.partition_point(|&(o, _)| o < offset);
+    let first = at.saturating_sub(CONTEXT);
+    let last = (at + CONTEXT + 1).min(lines.len());
+
+    lines[first..last]
+        .iter()
+        .map(|(o, l)| (*o == offset, l.replace('\t', " ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(file: &str, offset: u64) -> Hit {
+        Hit {
+            file: PathBuf::from(file),
+            offset,
+            line: OwnedLine {
+                timestamp: String::from("2020-06-22 11:00:00"),
+                nick: String::from("osse"),
+                message: String::from("hi hi"),
+            },
+            timestamp_matches: vec![(11, 13)],
+            nick_matches: vec![(1, 3)],
+            message_matches: vec![(0, 2), (3, 5)],
+        }
+    }
+
+    #[test]
+    fn hit_text() {
+        let (text, matches) = hit("a", 0).text();
+        assert_eq!(text, "2020-06-22 11:00:00 osse hi hi");
+        assert_eq!(matches, vec![(11, 13), (21, 23), (25, 27), (28, 30)]);
+    }
+
+    #[test]
+    fn context_lines() {
+        let lines: Vec<(u64, String)> = (0..20u64).map(|i| (i * 10, format!("{}\tx", i))).collect();
+
+        let context = context_around(&lines, 100);
+        assert_eq!(context.len(), 2 * CONTEXT + 1);
+        assert_eq!(context[CONTEXT], (true, String::from("10 x")));
+        assert_eq!(context[0], (false, String::from("5 x")));
+
+        let context = context_around(&lines, 0);
+        assert_eq!(context.len(), CONTEXT + 1);
+        assert!(context[0].0);
+    }
+
+    #[test]
+    fn file_navigation() {
+        let hits = vec![
+            hit("a", 1),
+            hit("a", 5),
+            hit("b", 2),
+            hit("c", 0),
+            hit("c", 3),
+        ];
+        let mut b = Browser::new(&hits, false, Encoding::Utf8);
+
+        b.next_file();
+        assert_eq!(b.selected, 2);
+        b.next_file();
+        assert_eq!(b.selected, 3);
+        b.next_file();
+        assert_eq!(b.selected, 3);
+
+        b.down();
+        assert_eq!(b.selected, 4);
+        b.down();
+        assert_eq!(b.selected, 4);
+
+        b.prev_file();
+        assert_eq!(b.selected, 3);
+        b.prev_file();
+        assert_eq!(b.selected, 2);
+        b.prev_file();
+        assert_eq!(b.selected, 0);
+        b.prev_file();
+        assert_eq!(b.selected, 0);
+
+        b.up();
+        assert_eq!(b.selected, 0);
+    }
+}